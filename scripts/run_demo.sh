@@ -7,15 +7,10 @@ COLS="${3:-160}"
 ASPECT="${4:-2.0}"
 AUDIO="${5:-false}"
 
-rm -rf frames out_txt
-mkdir -p frames out_txt
-
-echo "[1/4] ffmpeg extract frames…"
-ffmpeg -hide_banner -loglevel error -i "$INPUT" \
-  -vf "fps=${FPS},scale=${COLS}:-1:flags=area" frames/%06d.png
+rm -f out.asciimov
 
 if [ "$AUDIO" = "true" ] || [ "$AUDIO" = "audio" ]; then
-    echo "[2/4] ffmpeg extract & bitcrush audio…"
+    echo "[1/3] ffmpeg extract & bitcrush audio…"
     ffmpeg -hide_banner -loglevel error -i "$INPUT" -vn -ac 1 -ar 16000 \
       -af "lowpass=f=6000,acrusher=bits=8:mode=lin:mix=1" \
       -c:a pcm_u8 audio_8bit.wav
@@ -23,8 +18,10 @@ if [ "$AUDIO" = "true" ] || [ "$AUDIO" = "audio" ]; then
     echo "   Play it with: afplay audio_8bit.wav (macOS) or aplay audio_8bit.wav (Linux)"
 fi
 
-echo "[3/4] rust ascii batch…"
-cargo run --release --bin ascii-batch -- frames out_txt "$COLS" "$ASPECT" triangle basic
+echo "[2/3] ffmpeg → rust ascii batch (streamed, no frame images on disk)…"
+ffmpeg -hide_banner -loglevel error -i "$INPUT" \
+  -vf "fps=${FPS},scale=${COLS}:-2:flags=area" -pix_fmt yuv420p -f yuv4mpegpipe - \
+  | cargo run --release --bin ascii-batch -- - out.asciimov "$COLS" "$ASPECT" triangle basic
 
-echo "[4/4] play ascii animation…"
-cargo run --release --bin ascii-play -- out_txt "$FPS"
+echo "[3/3] play ascii animation…"
+cargo run --release --bin ascii-play -- out.asciimov
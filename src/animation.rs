@@ -0,0 +1,203 @@
+//! Animated GIF, APNG and WebP input, converted frame by frame
+
+use anyhow::{Context, Result};
+use crate::image_processing::{image_to_ascii, AsciiConfig};
+use crate::preprocess::Preprocess;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Delay, DynamicImage, ImageFormat, ImageReader};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+/// Frame delays this short or shorter are shown for [`DEFAULT_DELAY`], as
+/// browsers do; GIF delays are in units of 10 ms, so this catches 0 and 10
+const SHORT_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// One fully composited frame of an animation
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub image: DynamicImage,
+    /// How long the frame stays on screen
+    pub delay: Duration,
+}
+
+/// One converted frame of an animation
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsciiFrame {
+    pub text: String,
+    pub delay: Duration,
+}
+
+/// Frames of an image file, decoded as they are read; still images have one
+pub struct Frames {
+    buffered: VecDeque<Result<AnimationFrame>>,
+    rest: Box<dyn Iterator<Item = Result<AnimationFrame>>>,
+    /// Decoded from an animated GIF, APNG or WebP file
+    container: bool,
+}
+
+impl Frames {
+    /// Whether the file has more than one frame (decodes up to two frames of
+    /// an animation ahead; still images are not decoded)
+    pub fn is_animated(&mut self) -> bool {
+        if !self.container {
+            return false;
+        }
+        while self.buffered.len() < 2 {
+            match self.rest.next() {
+                Some(frame) => self.buffered.push_back(frame),
+                None => break,
+            }
+        }
+        self.buffered.len() > 1
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Result<AnimationFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffered.pop_front().or_else(|| self.rest.next())
+    }
+}
+
+/// Decode the frames of an animated GIF, APNG or WebP file, each composited
+/// onto the frames before it as the file's disposal methods say, then
+/// preprocessed. Other files decode to a single frame with no delay, decoded
+/// when it is first read.
+pub fn decode_frames(path: &Path, preprocess: &Preprocess) -> Result<Frames> {
+    let display = path.display().to_string();
+    let format = ImageReader::open(path)
+        .with_context(|| format!("failed to open {display}"))?
+        .with_guessed_format()?
+        .format();
+    let reader = || -> Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(path).with_context(|| format!("failed to open {display}"))?))
+    };
+    let decoded = match format {
+        Some(ImageFormat::Gif) => Some(GifDecoder::new(reader()?)?.into_frames()),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader()?)?;
+            match decoder.is_apng()? {
+                true => Some(decoder.apng()?.into_frames()),
+                false => None,
+            }
+        }
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader()?)?;
+            decoder.has_animation().then(|| decoder.into_frames())
+        }
+        _ => None,
+    };
+
+    let container = decoded.is_some();
+    let rest: Box<dyn Iterator<Item = Result<AnimationFrame>>> = match decoded {
+        Some(frames) => {
+            let preprocess = preprocess.clone();
+            Box::new(frames.map(move |frame| {
+                let frame = frame.with_context(|| format!("failed to decode {display}"))?;
+                let delay = frame_delay(frame.delay());
+                let image = preprocess.apply(DynamicImage::ImageRgba8(frame.into_buffer()))?;
+                Ok(AnimationFrame { image, delay })
+            }))
+        }
+        None => {
+            let (path, preprocess) = (path.to_path_buf(), preprocess.clone());
+            Box::new(std::iter::once_with(move || Ok(AnimationFrame { image: preprocess.open(&path)?, delay: Duration::ZERO })))
+        }
+    };
+    Ok(Frames { buffered: VecDeque::new(), rest, container })
+}
+
+/// How long a decoded frame stays on screen. Zero or near-zero delays get
+/// [`DEFAULT_DELAY`] so that every frame is shown for some time.
+fn frame_delay(delay: Delay) -> Duration {
+    let (numer, denom) = delay.numer_denom_ms();
+    let delay = Duration::from_secs_f64(numer as f64 / denom.max(1) as f64 / 1000.0);
+    if delay <= SHORT_DELAY { DEFAULT_DELAY } else { delay }
+}
+
+/// Convert every frame with [`image_to_ascii`]
+pub fn animation_to_ascii(frames: impl Iterator<Item = Result<AnimationFrame>>, config: &AsciiConfig) -> Result<Vec<AsciiFrame>> {
+    frames
+        .map(|frame| {
+            let frame = frame?;
+            Ok(AsciiFrame { text: image_to_ascii(&frame.image, config)?, delay: frame.delay })
+        })
+        .collect()
+}
+
+/// Write frames to `dir` as `%06d.txt` files for `ascii-play` at `fps`,
+/// repeating or dropping frames so each keeps its delay. Returns the number
+/// of files written.
+pub fn write_frame_dir(dir: &Path, frames: &[AsciiFrame], fps: u32) -> Result<usize> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let total: Duration = frames.iter().map(|f| f.delay).sum();
+    let count = ((total.as_secs_f64() * fps as f64).round() as usize).max(1);
+
+    let (mut current, mut end) = (0, frames.first().map_or(Duration::ZERO, |f| f.delay));
+    for k in 0..count {
+        // The frame on screen at this tick
+        let t = Duration::from_secs_f64(k as f64 / fps as f64);
+        while t >= end && current + 1 < frames.len() {
+            current += 1;
+            end += frames[current].delay;
+        }
+        let name = dir.join(format!("{:06}.txt", k + 1));
+        fs::write(&name, &frames[current].text).with_context(|| format!("write {}", name.display()))?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Frame, Rgba, RgbaImage};
+
+    #[test]
+    fn test_decode_gif_frames() {
+        let path = std::env::temp_dir().join(format!("rust-ascii-anim-{}.gif", std::process::id()));
+        let frames = [(0u8, 250), (255, 0)].map(|(luma, ms)| {
+            Frame::from_parts(RgbaImage::from_pixel(8, 4, Rgba([luma, luma, luma, 255])), 0, 0, Delay::from_numer_denom_ms(ms, 1))
+        });
+        GifEncoder::new(File::create(&path).unwrap()).encode_frames(frames).unwrap();
+
+        let mut decoded = decode_frames(&path, &Preprocess::new().rotate(90.0)).unwrap();
+        assert!(decoded.is_animated());
+        let config = AsciiConfig { cols: 2, ramp: vec![' ', '@'], ..AsciiConfig::default() };
+        let ascii = animation_to_ascii(decoded, &config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // 4×8 after rotation: 2 columns by 2 rows of 2:1 cells
+        assert_eq!(ascii.iter().map(|f| f.text.as_str()).collect::<Vec<_>>(), ["  \n  \n", "@@\n@@\n"]);
+        assert_eq!(ascii[0].delay, Duration::from_millis(250));
+        // No delay counts as the browser default
+        assert_eq!(ascii[1].delay, DEFAULT_DELAY);
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        // The same default for every format, so no frame is dropped from a
+        // --frames-dir for having no time on screen; 60 fps APNG frames stay
+        let delay = |ms| frame_delay(Delay::from_numer_denom_ms(ms, 1));
+        assert_eq!([0, 10, 16, 250].map(delay), [100, 100, 16, 250].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn test_write_frame_dir_keeps_delays() {
+        let dir = std::env::temp_dir().join(format!("rust-ascii-frames-{}", std::process::id()));
+        let frame = |text: &str, ms| AsciiFrame { text: text.to_string(), delay: Duration::from_millis(ms) };
+        let written = write_frame_dir(&dir, &[frame("a", 110), frame("b", 20), frame("c", 70)], 20).unwrap();
+        let texts: Vec<_> = (1..=written).map(|k| fs::read_to_string(dir.join(format!("{k:06}.txt"))).unwrap()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Ticks every 50 ms: "b" falls between two ticks and is dropped
+        assert_eq!(texts, ["a", "a", "a", "c"]);
+    }
+}
@@ -0,0 +1,189 @@
+//! ANSI escape sequence helpers for colored terminal output
+
+use anyhow::Result;
+use crate::palette::{nearest_ansi16, nearest_ansi256, xterm_rgb};
+use unicode_width::UnicodeWidthChar;
+
+/// SGR sequence that resets all colors and attributes
+pub const RESET: &str = "\x1b[0m";
+
+/// Color output mode for ASCII conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    /// Plain text, no escape sequences
+    #[default]
+    None,
+    /// Basic 16-color palette (`ESC[30-37m` / `ESC[90-97m`)
+    Ansi16,
+    /// xterm-256 color cube and grayscale ramp (`ESC[38;5;nm`)
+    Ansi256,
+    /// 24-bit ANSI escapes (`ESC[38;2;r;g;bm`)
+    Truecolor,
+}
+
+impl ColorMode {
+    /// Quantize an RGB color to what this mode can display
+    pub fn quantize(self, rgb: [u8; 3]) -> Option<AnsiColor> {
+        match self {
+            ColorMode::None => None,
+            ColorMode::Ansi16 => Some(AnsiColor::Indexed(nearest_ansi16(rgb))),
+            ColorMode::Ansi256 => Some(AnsiColor::Indexed(nearest_ansi256(rgb))),
+            ColorMode::Truecolor => Some(AnsiColor::Rgb(rgb)),
+        }
+    }
+}
+
+/// A terminal color: an xterm palette index or a 24-bit RGB value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor {
+    Indexed(u8),
+    Rgb([u8; 3]),
+}
+
+impl AnsiColor {
+    /// The RGB value this color displays as (xterm defaults for palette colors)
+    pub fn rgb(self) -> [u8; 3] {
+        match self {
+            AnsiColor::Indexed(n) => xterm_rgb(n),
+            AnsiColor::Rgb(rgb) => rgb,
+        }
+    }
+}
+
+/// Select the color mode based on its CLI name
+pub fn select_color_mode(name: &str) -> Result<ColorMode> {
+    Ok(match name {
+        "none" => ColorMode::None,
+        "16" => ColorMode::Ansi16,
+        "256" => ColorMode::Ansi256,
+        "truecolor" | "24bit" => ColorMode::Truecolor,
+        "auto" => detect_color_mode(),
+        other => anyhow::bail!("unknown color mode: {other}. Available options: auto, none, 16, 256, truecolor"),
+    })
+}
+
+/// Detect the best supported color mode from the `COLORTERM` and `TERM` environment variables
+pub fn detect_color_mode() -> ColorMode {
+    color_mode_from_env(
+        std::env::var("COLORTERM").ok().as_deref(),
+        std::env::var("TERM").ok().as_deref(),
+    )
+}
+
+fn color_mode_from_env(colorterm: Option<&str>, term: Option<&str>) -> ColorMode {
+    if matches!(colorterm, Some("truecolor" | "24bit")) {
+        return ColorMode::Truecolor;
+    }
+    match term {
+        Some("dumb") => ColorMode::None,
+        Some(t) if t.contains("direct") => ColorMode::Truecolor,
+        Some(t) if t.contains("256color") => ColorMode::Ansi256,
+        _ => ColorMode::Ansi16,
+    }
+}
+
+/// Append a cursor-forward escape that skips `n` columns without drawing
+/// them. Nothing is appended for 0, which terminals would take as 1.
+pub fn push_skip(out: &mut String, n: usize) {
+    if n > 0 {
+        out.push_str(&format!("\x1b[{n}C"));
+    }
+}
+
+/// Append a foreground color escape to `out`
+pub fn push_fg(out: &mut String, color: AnsiColor) {
+    use std::fmt::Write;
+    let _ = match color {
+        AnsiColor::Indexed(n @ 0..=7) => write!(out, "\x1b[{}m", 30 + n as u32),
+        AnsiColor::Indexed(n @ 8..=15) => write!(out, "\x1b[{}m", 90 + n as u32 - 8),
+        AnsiColor::Indexed(n) => write!(out, "\x1b[38;5;{n}m"),
+        AnsiColor::Rgb([r, g, b]) => write!(out, "\x1b[38;2;{r};{g};{b}m"),
+    };
+}
+
+/// Append a background color escape to `out`
+pub fn push_bg(out: &mut String, color: AnsiColor) {
+    use std::fmt::Write;
+    let _ = match color {
+        AnsiColor::Indexed(n @ 0..=7) => write!(out, "\x1b[{}m", 40 + n as u32),
+        AnsiColor::Indexed(n @ 8..=15) => write!(out, "\x1b[{}m", 100 + n as u32 - 8),
+        AnsiColor::Indexed(n) => write!(out, "\x1b[48;5;{n}m"),
+        AnsiColor::Rgb([r, g, b]) => write!(out, "\x1b[48;2;{r};{g};{b}m"),
+    };
+}
+
+/// Display width of a line in terminal columns, skipping ANSI escape sequences
+pub fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequence: ESC '[' params final-byte; cursor-forward skips columns
+            let mut params = String::new();
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) && c != '[' {
+                    if c == 'C' {
+                        width += params.parse::<usize>().unwrap_or(1).max(1);
+                    }
+                    break;
+                }
+                if c != '[' {
+                    params.push(c);
+                }
+            }
+        } else {
+            width += c.width().unwrap_or(0);
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_color_mode() {
+        assert_eq!(select_color_mode("none").unwrap(), ColorMode::None);
+        assert_eq!(select_color_mode("truecolor").unwrap(), ColorMode::Truecolor);
+        assert_eq!(select_color_mode("256").unwrap(), ColorMode::Ansi256);
+        assert!(select_color_mode("rainbow").is_err());
+    }
+
+    #[test]
+    fn test_color_mode_from_env() {
+        assert_eq!(color_mode_from_env(Some("truecolor"), Some("xterm")), ColorMode::Truecolor);
+        assert_eq!(color_mode_from_env(None, Some("screen-256color")), ColorMode::Ansi256);
+        assert_eq!(color_mode_from_env(None, Some("xterm")), ColorMode::Ansi16);
+        assert_eq!(color_mode_from_env(None, Some("dumb")), ColorMode::None);
+    }
+
+    #[test]
+    fn test_push_fg_bg() {
+        let mut out = String::new();
+        push_fg(&mut out, AnsiColor::Indexed(1));
+        push_fg(&mut out, AnsiColor::Indexed(9));
+        push_fg(&mut out, AnsiColor::Indexed(196));
+        push_bg(&mut out, AnsiColor::Indexed(4));
+        push_bg(&mut out, AnsiColor::Rgb([1, 2, 3]));
+        assert_eq!(out, "\x1b[31m\x1b[91m\x1b[38;5;196m\x1b[44m\x1b[48;2;1;2;3m");
+    }
+
+    #[test]
+    fn test_visible_width() {
+        let mut line = String::new();
+        push_fg(&mut line, AnsiColor::Rgb([255, 0, 0]));
+        line.push_str("ab");
+        line.push_str(RESET);
+        assert_eq!(visible_width(&line), 2);
+        assert_eq!(visible_width("plain"), 5);
+        assert_eq!(visible_width("中文"), 4);
+
+        let mut skipped = String::from("a");
+        push_skip(&mut skipped, 3);
+        skipped.push('b');
+        assert_eq!(visible_width(&skipped), 5);
+        push_skip(&mut skipped, 0);
+        assert_eq!(skipped, "a\x1b[3Cb");
+    }
+}
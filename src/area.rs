@@ -0,0 +1,207 @@
+//! Area-averaging and pooling samplers that integrate over each sample's
+//! full source footprint, straight from the original buffer
+
+use crate::sampler::{Grid, Sampler};
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use std::ops::Range;
+
+/// How the source pixels under one sample are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pooling {
+    /// Coverage-weighted average, counting partially covered pixels fractionally
+    #[default]
+    Mean,
+    /// Darkest value of each channel
+    Min,
+    /// Brightest value of each channel
+    Max,
+    /// Median of each channel
+    Median,
+}
+
+/// Samples each cell from every source pixel its footprint touches
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AreaSampler {
+    pub pooling: Pooling,
+}
+
+/// Source pixels overlapped by one sample along one axis: the first index and
+/// the covered fraction of each pixel from there on
+pub(crate) struct Span {
+    pub(crate) first: usize,
+    pub(crate) weights: Vec<f32>,
+}
+
+/// Footprints of `n_out` samples over `n_src` pixels, where `bounds` gives the
+/// start and end of each sample in source coordinates
+pub(crate) fn spans(n_out: u32, n_src: u32, bounds: impl Fn(u32) -> (f32, f32)) -> Vec<Span> {
+    let max = n_src as f32;
+    (0..n_out)
+        .map(|o| {
+            let (a, b) = bounds(o);
+            let (a, b) = (a.clamp(0.0, max), b.clamp(0.0, max));
+            if b - a < 1e-6 {
+                // Footprint outside the image (rounding of the row count): repeat the edge
+                return Span { first: (a as usize).min(n_src as usize - 1), weights: vec![1.0] };
+            }
+            let first = a.floor() as usize;
+            let last = (b.ceil() as usize).min(n_src as usize) - 1;
+            let weights = (first..=last)
+                .map(|i| b.min(i as f32 + 1.0) - a.max(i as f32))
+                .collect();
+            Span { first, weights }
+        })
+        .collect()
+}
+
+/// Pool an interleaved buffer of `channels` values per pixel, `stride` pixels
+/// wide, onto grid rows `rows` of the `(x, y, width, height)` region, returning
+/// `channels` floats per sample
+fn pool<T: Copy + Into<f32>>(
+    data: &[T],
+    stride: u32,
+    (x0, y0, w, h): (u32, u32, u32, u32),
+    channels: usize,
+    grid: &Grid,
+    rows: Range<u32>,
+    pooling: Pooling,
+) -> Vec<f32> {
+    let xs = spans(grid.width, w, |x| (x as f32 / grid.scale_x, (x + 1) as f32 / grid.scale_x));
+    let ys = spans(rows.len() as u32, h, |y| {
+        let y = y + rows.start;
+        (y as f32 / grid.scale_y * grid.cell_aspect, (y + 1) as f32 / grid.scale_y * grid.cell_aspect)
+    });
+    let px = |x: usize, y: usize, c: usize| data[((y + y0 as usize) * stride as usize + x + x0 as usize) * channels + c].into();
+
+    let mut out = Vec::with_capacity(xs.len() * ys.len() * channels);
+    if pooling == Pooling::Mean {
+        // Separable: average each source row across the column footprints,
+        // then average those rows down the row footprints, one output row at a time
+        let stride = xs.len() * channels;
+        let mut row = Vec::with_capacity(stride);
+        let mut sums = vec![0.0f32; stride];
+        for span in &ys {
+            sums.fill(0.0);
+            for (i, wt) in span.weights.iter().enumerate() {
+                let y = span.first + i;
+                row.clear();
+                row.extend(xs.iter().flat_map(|xs| {
+                    let total: f32 = xs.weights.iter().sum();
+                    (0..channels).map(move |c| xs.weights.iter().enumerate().map(|(i, wt)| wt * px(xs.first + i, y, c)).sum::<f32>() / total)
+                }));
+                sums.iter_mut().zip(&row).for_each(|(sum, value)| *sum += wt * value);
+            }
+            let total: f32 = span.weights.iter().sum();
+            out.extend(sums.iter().map(|sum| sum / total));
+        }
+        return out;
+    }
+
+    let mut values = Vec::new();
+    for ys in &ys {
+        for xs in &xs {
+            for c in 0..channels {
+                values.clear();
+                for y in ys.first..ys.first + ys.weights.len() {
+                    for x in xs.first..xs.first + xs.weights.len() {
+                        values.push(px(x, y, c));
+                    }
+                }
+                out.push(match pooling {
+                    Pooling::Min => values.iter().copied().fold(f32::INFINITY, f32::min),
+                    Pooling::Max => values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+                    _ => {
+                        let mid = values.len() / 2;
+                        *values.select_nth_unstable_by(mid, f32::total_cmp).1
+                    }
+                });
+            }
+        }
+    }
+    out
+}
+
+impl AreaSampler {
+    /// Sample grid rows `rows` of the `(x, y, width, height)` region of
+    /// `img`, reading only the source rows under them
+    pub(crate) fn sample_rows(&self, img: &DynamicImage, region: (u32, u32, u32, u32), grid: &Grid, rows: Range<u32>) -> DynamicImage {
+        let stride = img.width();
+        let (width, height) = (grid.width, rows.len() as u32);
+
+        // Linear-light images stay in floating point
+        if let DynamicImage::ImageRgb32F(buf) = img {
+            let out = pool(buf.as_raw(), stride, region, 3, grid, rows, self.pooling);
+            return DynamicImage::ImageRgb32F(ImageBuffer::from_fn(width, height, |x, y| {
+                let i = ((y * width + x) * 3) as usize;
+                Rgb([out[i], out[i + 1], out[i + 2]])
+            }));
+        }
+
+        // 8-bit images are read in place; other depths are converted once
+        let converted;
+        let (data, channels) = match img {
+            DynamicImage::ImageLuma8(buf) => (buf.as_raw(), 1),
+            DynamicImage::ImageLumaA8(buf) => (buf.as_raw(), 2),
+            DynamicImage::ImageRgb8(buf) => (buf.as_raw(), 3),
+            DynamicImage::ImageRgba8(buf) => (buf.as_raw(), 4),
+            other => {
+                converted = other.to_rgba8();
+                (converted.as_raw(), 4)
+            }
+        };
+        let out = pool(data, stride, region, channels, grid, rows, self.pooling);
+        let byte = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |x, y| {
+            let i = ((y * width + x) as usize) * channels;
+            let s = &out[i..i + channels];
+            Rgba(match channels {
+                1 => [byte(s[0]), byte(s[0]), byte(s[0]), 255],
+                2 => [byte(s[0]), byte(s[0]), byte(s[0]), byte(s[1])],
+                3 => [byte(s[0]), byte(s[1]), byte(s[2]), 255],
+                _ => [byte(s[0]), byte(s[1]), byte(s[2]), byte(s[3])],
+            })
+        }))
+    }
+}
+
+impl Sampler for AreaSampler {
+    fn sample(&self, img: &DynamicImage, grid: &Grid) -> DynamicImage {
+        self.sample_rows(img, (0, 0, img.width(), img.height()), grid, 0..grid.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    fn grid(width: u32, height: u32, src: (u32, u32)) -> Grid {
+        let scale_x = width as f32 / src.0 as f32;
+        Grid { width, height, scale_x, scale_y: scale_x, cell_aspect: scale_x * src.1 as f32 / height as f32 }
+    }
+
+    #[test]
+    fn test_area_mean_has_no_aliasing() {
+        // A 1-pixel checkerboard averages to mid-gray instead of aliasing to black or white
+        let checker = DynamicImage::ImageLuma8(GrayImage::from_fn(30, 30, |x, y| Luma([if (x + y) % 2 == 0 { 0 } else { 255 }])));
+        let sampled = AreaSampler::default().sample(&checker, &grid(4, 4, (30, 30))).to_luma8();
+        assert!(sampled.pixels().all(|p| p[0].abs_diff(128) <= 10));
+    }
+
+    #[test]
+    fn test_area_mean_weights_partial_pixels() {
+        // Three source pixels onto two samples: each sample covers 1.5 pixels
+        let row = DynamicImage::ImageLuma8(GrayImage::from_raw(3, 1, vec![0, 90, 180]).unwrap());
+        let sampled = AreaSampler::default().sample(&row, &grid(2, 1, (3, 1))).to_luma8();
+        assert_eq!(sampled.as_raw(), &vec![30, 150]);
+    }
+
+    #[test]
+    fn test_pooling() {
+        let img = DynamicImage::ImageLuma8(GrayImage::from_raw(4, 1, vec![10, 200, 50, 60]).unwrap());
+        let run = |pooling| AreaSampler { pooling }.sample(&img, &grid(1, 1, (4, 1))).to_luma8().get_pixel(0, 0)[0];
+        assert_eq!(run(Pooling::Min), 10);
+        assert_eq!(run(Pooling::Max), 200);
+        assert_eq!(run(Pooling::Median), 60);
+    }
+}
@@ -1,15 +1,118 @@
 use anyhow::{Context, Result};
-use image::ImageReader;
+use clap::Parser;
 use rayon::prelude::*;
-use std::{fs, path::{Path, PathBuf}};
-use rust_ascii::{select_filter, select_ramp, image_to_ascii};
-
-#[derive(Clone)]
-struct Params {
-    cols: u32,
-    cell_aspect: f32,
-    resizer_name: String,
-    ramp: Vec<u8>,
+use image::{DynamicImage, GenericImageView};
+use std::{fs, fs::File, time::Duration, io::{self, BufRead, BufReader, BufWriter}, path::{Path, PathBuf}};
+use rust_ascii::{output_size, MovieHeader, MovieWriter, parse_frame_size, select_pixel_format, VideoReader, ClaheConfig, TileHistograms, clahe_histograms, image_to_grid, ConvertArgs, Preprocess, AsciiConfig, Dither};
+
+#[derive(Parser)]
+#[command(name = "ascii-batch")]
+#[command(about = "Convert a directory of PNG frames or a Y4M/raw video stream to ASCII text frames in parallel")]
+#[command(version)]
+struct Args {
+    /// Directory containing %06d.png frames, or a YUV4MPEG2 (or --raw) video file; `-` reads stdin
+    #[arg(default_value = "frames")]
+    frames_dir: PathBuf,
+
+    /// Directory to write %06d.txt frames into, or a .asciimov movie file
+    #[arg(default_value = "out_txt")]
+    out_dir: PathBuf,
+
+    /// Number of columns in the output, as --cols [default: 160]
+    #[arg(value_name = "COLS")]
+    cols_arg: Option<u32>,
+
+    /// Terminal cell aspect ratio (height/width), as --cell-aspect [default: 2.0]
+    #[arg(value_name = "CELL_ASPECT")]
+    cell_aspect_arg: Option<f32>,
+
+    /// Resampling filter to use, as --resizer [default: triangle]
+    #[arg(value_name = "RESIZER")]
+    resizer_arg: Option<String>,
+
+    /// Glyph ramp for mapping luminance to characters, as --ramp [default: basic]
+    #[arg(value_name = "RAMP")]
+    ramp_arg: Option<String>,
+
+    #[command(flatten)]
+    convert: ConvertArgs,
+
+    /// Share of the previous frames' CLAHE histograms kept per frame (0.0-1.0), to avoid pumping
+    #[arg(long, default_value_t = 0.8)]
+    clahe_smoothing: f32,
+
+    /// Read headerless frames of this pixel format (rgb24, gray) instead of YUV4MPEG2
+    #[arg(long)]
+    raw: Option<String>,
+
+    /// Frame size of --raw input (e.g. 640x360)
+    #[arg(long)]
+    size: Option<String>,
+
+    /// Frame rate recorded in a .asciimov movie [default: the Y4M stream's, else 30]
+    #[arg(long)]
+    fps: Option<u32>,
+
+    /// Store .asciimov frames uncompressed
+    #[arg(long)]
+    no_compress: bool,
+
+    /// Store a full .asciimov keyframe every N frames and only the changed cells in between
+    #[arg(long, default_value_t = 60)]
+    keyframe_interval: u32,
+}
+
+/// Where converted frames go
+enum Output {
+    /// `%06d.txt` files in a directory
+    Dir(PathBuf),
+    /// A movie file, created once the first frame's size is known
+    Movie(PathBuf, Option<MovieWriter<BufWriter<File>>>),
+}
+
+/// Where frames come from
+enum Input {
+    /// PNG files, decoded in parallel
+    Pngs(std::vec::IntoIter<PathBuf>),
+    /// A video stream, read in order
+    Video(VideoReader<Box<dyn BufRead>>),
+}
+
+impl Input {
+    fn open(source: &Path, raw: Option<&str>, size: Option<&str>) -> Result<Self> {
+        if source.is_dir() {
+            let frames = list_pngs(source)?;
+            if frames.is_empty() {
+                anyhow::bail!("no PNG frames found in {}", source.display());
+            }
+            return Ok(Input::Pngs(frames.into_iter()));
+        }
+        let reader: Box<dyn BufRead> = match source.to_str() {
+            Some("-") => Box::new(BufReader::with_capacity(1 << 20, io::stdin())),
+            _ => Box::new(BufReader::with_capacity(1 << 20, File::open(source).with_context(|| format!("open {}", source.display()))?)),
+        };
+        Ok(Input::Video(match raw {
+            Some(format) => {
+                let size = size.context("--raw needs the frame --size (e.g. 640x360)")?;
+                VideoReader::raw(reader, select_pixel_format(format)?, parse_frame_size(size)?)?
+            }
+            None => VideoReader::y4m(reader).with_context(|| format!("read {}", source.display()))?,
+        }))
+    }
+
+    /// Decode and preprocess up to `n` more frames, in order
+    fn next_chunk(&mut self, n: usize, preprocess: &Preprocess) -> Result<Vec<DynamicImage>> {
+        match self {
+            Input::Pngs(paths) => {
+                let chunk: Vec<PathBuf> = paths.by_ref().take(n).collect();
+                chunk.par_iter().map(|path| preprocess.open(path)).collect()
+            }
+            Input::Video(reader) => {
+                let frames = reader.by_ref().take(n).collect::<Result<Vec<_>>>()?;
+                frames.into_par_iter().map(|frame| preprocess.apply(frame)).collect()
+            }
+        }
+    }
 }
 
 fn list_pngs(dir: &Path) -> Result<Vec<PathBuf>> {
@@ -23,50 +126,130 @@ fn list_pngs(dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(v)
 }
 
+fn write_frame(out_dir: &Path, index: usize, ascii: &str) -> Result<()> {
+    let name = out_dir.join(format!("{:06}.txt", index + 1));
+    fs::write(&name, ascii).with_context(|| format!("write {}", name.display()))
+}
+
 fn main() -> Result<()> {
-    // Simple CLI via env args: ascii-batch [frames_dir] [out_dir] [cols] [cell_aspect] [resizer] [ramp]
-    let args: Vec<String> = std::env::args().collect();
-    let frames_dir = PathBuf::from(args.get(1).cloned().unwrap_or_else(|| "frames".into()));
-    let out_dir    = PathBuf::from(args.get(2).cloned().unwrap_or_else(|| "out_txt".into()));
-    let cols: u32  = args.get(3).and_then(|s| s.parse().ok()).unwrap_or(160);
-    let cell_aspect: f32 = args.get(4).and_then(|s| s.parse().ok()).unwrap_or(2.0);
-    let resizer_name = args.get(5).cloned().unwrap_or_else(|| "triangle".into());
-    let ramp_spec    = args.get(6).cloned().unwrap_or_else(|| "basic".into());
-
-    fs::create_dir_all(&out_dir).with_context(|| format!("creating {}", out_dir.display()))?;
-
-    let _filter = select_filter(&resizer_name)?;
-    let ramp = select_ramp(&ramp_spec)?;
-
-    let params = Params { cols, cell_aspect, resizer_name, ramp };
-    let frames = list_pngs(&frames_dir)?;
-    if frames.is_empty() {
-        anyhow::bail!("no PNG frames found in {}", frames_dir.display());
+    // CLI: ascii-batch [frames_dir] [out_dir] [cols] [cell_aspect] [resizer] [ramp] [--color] [--dither] [--mode] [--edges] [--crop] [--rotate] [--raw --size] [--fps] [--keyframe-interval]
+    let args = Args::parse();
+    let frames_dir = args.frames_dir;
+    let out_dir = args.out_dir;
+
+    let mut output = match out_dir.extension().is_some_and(|ext| ext == "asciimov") {
+        true => Output::Movie(out_dir.clone(), None),
+        false => {
+            fs::create_dir_all(&out_dir).with_context(|| format!("creating {}", out_dir.display()))?;
+            Output::Dir(out_dir.clone())
+        }
+    };
+
+    if args.keyframe_interval == 0 {
+        anyhow::bail!("--keyframe-interval must be at least 1");
+    }
+    if args.fps == Some(0) {
+        anyhow::bail!("--fps must be at least 1");
+    }
+    // The positional forms of the first few settings take precedence over the flags
+    let mut convert = args.convert;
+    convert.cols = args.cols_arg.or(convert.cols);
+    convert.cell_aspect = args.cell_aspect_arg.unwrap_or(convert.cell_aspect);
+    convert.resizer = args.resizer_arg.unwrap_or(convert.resizer);
+    convert.ramp = args.ramp_arg.unwrap_or(convert.ramp);
+    let config = convert.to_config(None, 160)?;
+    let preprocess = convert.to_preprocess()?;
+    if config.dither != Dither::None && !config.dither.is_ordered() {
+        eprintln!("⚠️  Error-diffusion dithering shimmers between frames; bayer4/bayer8/blue-noise are stable");
+    }
+
+    let mut input = Input::open(&frames_dir, args.raw.as_deref(), args.size.as_deref())?;
+    match &input {
+        Input::Pngs(frames) => eprintln!("Converting {} frames → {} (parallel)…", frames.len(), out_dir.display()),
+        Input::Video(video) => {
+            let (w, h) = video.dimensions();
+            let rate = video.fps().map(|(n, d)| format!(" at {:.3} fps", n as f64 / d as f64)).unwrap_or_default();
+            eprintln!("Converting {w}×{h} video{rate} → {} (parallel)…", out_dir.display());
+        }
+    }
+
+    let video_fps = match &input {
+        Input::Video(video) => video.fps(),
+        Input::Pngs(_) => None,
+    };
+    let fps = args.fps.map(|fps| (fps, 1)).or(video_fps).unwrap_or((30, 1));
+    let frame_time = Duration::from_secs_f64(fps.1 as f64 / fps.0 as f64);
+
+    if config.clahe.is_some() && !(0.0..=1.0).contains(&args.clahe_smoothing) {
+        anyhow::bail!("--clahe-smoothing should be between 0.0 and 1.0");
+    }
+    let smoothing = config.clahe.is_some() && args.clahe_smoothing > 0.0;
+
+    // Frames are decoded a chunk at a time and converted in parallel, then
+    // written in order. With CLAHE smoothing, histograms are computed in
+    // parallel and smoothed in frame order before the frames render.
+    let chunk_size = rayon::current_num_threads() * 2;
+    let mut previous: Option<TileHistograms> = None;
+    let mut written = 0;
+    loop {
+        let images = input.next_chunk(chunk_size, &preprocess)?;
+        if images.is_empty() {
+            break;
+        }
+        if let Output::Movie(path, writer @ None) = &mut output {
+            let (cols, rows) = output_size(&config, images[0].dimensions());
+            let header = MovieHeader {
+                cols,
+                rows,
+                cell_aspect: config.cell_aspect,
+                fps: Some(fps),
+                color: config.color,
+                ramp: config.ramp.iter().collect(),
+                compressed: !args.no_compress,
+                mode: config.mode,
+                source_size: images[0].dimensions(),
+                keyframe_interval: Some(args.keyframe_interval),
+            };
+            *writer = Some(MovieWriter::create(path, &header)?);
+        }
+        let mut histograms: Vec<_> = match smoothing {
+            true => images.par_iter().map(|img| clahe_histograms(img, &config)).collect(),
+            false => vec![None; images.len()],
+        };
+        if smoothing {
+            for histogram in histograms.iter_mut().flatten() {
+                if let Some(previous) = &previous {
+                    histogram.blend(previous, args.clahe_smoothing);
+                }
+                previous = Some(histogram.clone());
+            }
+        }
+
+        let frames = images.par_iter().zip(histograms).map(|(img, histograms)| match smoothing {
+            true => {
+                let config = AsciiConfig {
+                    clahe: config.clahe.clone().map(|clahe| ClaheConfig { histograms, ..clahe }),
+                    ..config.clone()
+                };
+                image_to_grid(img, &config)
+            }
+            false => image_to_grid(img, &config),
+        }).collect::<Result<Vec<_>>>()?;
+        for grid in frames {
+            match &mut output {
+                Output::Dir(dir) => write_frame(dir, written, &grid.to_ansi())?,
+                Output::Movie(_, writer) => writer.as_mut().expect("created above").write_grid(&grid, frame_time)?,
+            }
+            written += 1;
+        }
+    }
+    if written == 0 {
+        anyhow::bail!("no frames found in {}", frames_dir.display());
+    }
+    if let Output::Movie(path, Some(writer)) = output {
+        writer.finish().with_context(|| format!("write {}", path.display()))?;
     }
 
-    eprintln!("Converting {} frames → {} (parallel)…", frames.len(), out_dir.display());
-
-    // Convert in parallel
-    frames.par_iter().enumerate().try_for_each(|(i, path)| -> Result<()> {
-        let img = ImageReader::open(path)
-            .with_context(|| format!("open {}", path.display()))?
-            .decode()
-            .with_context(|| format!("decode {}", path.display()))?;
-
-        let s = image_to_ascii(
-            &img,
-            params.cols,
-            params.cell_aspect,
-            select_filter(&params.resizer_name)?,
-            &params.resizer_name,
-            params.ramp.clone(),
-        )?;
-
-        let name = out_dir.join(format!("{:06}.txt", i + 1));
-        fs::write(&name, s).with_context(|| format!("write {}", name.display()))?;
-        Ok(())
-    })?;
-
-    eprintln!("✅ Done.");
+    eprintln!("✅ Done: {written} frames.");
     Ok(())
 }
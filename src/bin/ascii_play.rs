@@ -1,16 +1,32 @@
 use anyhow::{Context, Result};
-use crossterm::{
-    cursor::MoveTo,
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, Clear, ClearType},
-};
+use clap::Parser;
+use rust_ascii::{play, AsciiFrame, MovieReader, PlaybackStats};
 use std::{
     fs,
-    io::{stdout, Write},
     path::{Path, PathBuf},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
+#[derive(Parser)]
+#[command(name = "ascii-play")]
+#[command(about = "Play a directory of ASCII frames or an .asciimov movie in the terminal")]
+struct Args {
+    /// Directory containing .txt frames, or an .asciimov movie
+    #[arg(default_value = "out_txt")]
+    txt_dir: PathBuf,
+
+    /// Playback frames per second [default: 30, or the movie's own timing]
+    fps: Option<u32>,
+
+    /// Start this many seconds in
+    #[arg(long, default_value_t = 0.0)]
+    start: f64,
+
+    /// Swap foreground and background (reverse video) for light-background terminals
+    #[arg(long)]
+    invert: bool,
+}
+
 fn list_txts(dir: &Path) -> Result<Vec<PathBuf>> {
     let mut v: Vec<_> = fs::read_dir(dir)
         .with_context(|| format!("reading {}", dir.display()))?
@@ -22,38 +38,55 @@ fn list_txts(dir: &Path) -> Result<Vec<PathBuf>> {
     Ok(v)
 }
 
-fn main() -> Result<()> {
-    // ascii-play [txt_dir] [fps]
-    let args: Vec<String> = std::env::args().collect();
-    let txt_dir = PathBuf::from(args.get(1).cloned().unwrap_or_else(|| "out_txt".into()));
-    let fps: u32 = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(30);
+/// Print how much playback wrote to the terminal
+fn report(stats: PlaybackStats) {
+    eprintln!(
+        "{} frames in {:.1}s: {:.1} KiB written, {:.1} KiB/s",
+        stats.frames,
+        stats.elapsed.as_secs_f64(),
+        stats.bytes as f64 / 1024.0,
+        stats.bytes_per_second() / 1024.0
+    );
+}
 
-    let frames = list_txts(&txt_dir)?;
-    if frames.is_empty() {
-        anyhow::bail!("no .txt frames found in {}", txt_dir.display());
+fn main() -> Result<()> {
+    // ascii-play [txt_dir|movie.asciimov] [fps] [--start] [--invert]
+    let args = Args::parse();
+    if !(args.start.is_finite() && args.start >= 0.0) {
+        anyhow::bail!("--start must be a number of seconds from the beginning");
     }
+    let start = Duration::from_secs_f64(args.start);
+    // An explicit fps replaces the movie's timing
+    let frame_time = args.fps.map(|fps| Duration::from_millis((1000 / fps.max(1)).max(1) as u64));
 
-    let frame_time = Duration::from_millis((1000 / fps).max(1) as u64);
-    let mut out = stdout();
-
-    execute!(out, EnterAlternateScreen)?;
-    let start = Instant::now();
-
-    for (i, path) in frames.iter().enumerate() {
-        let ascii = fs::read_to_string(path)
-            .with_context(|| format!("read {}", path.display()))?;
-        execute!(out, Clear(ClearType::All), MoveTo(0, 0))?;
-        out.write_all(ascii.as_bytes())?;
-        out.flush()?;
-
-        // maintain cadence
-        let deadline = start + frame_time * (i as u32 + 1);
-        let now = Instant::now();
-        if deadline > now {
-            std::thread::sleep(deadline - now);
+    if args.txt_dir.is_file() {
+        let mut movie = MovieReader::open(&args.txt_dir)?;
+        if movie.is_empty() {
+            anyhow::bail!("{} has no frames", args.txt_dir.display());
         }
+        let first = match frame_time {
+            Some(frame_time) => (start.as_secs_f64() / frame_time.as_secs_f64()) as usize,
+            None => movie.seek(start),
+        };
+        let frames = (first..movie.len()).map(move |i| {
+            let frame = movie.frame(i)?;
+            Ok(AsciiFrame { delay: frame_time.unwrap_or(frame.delay), ..frame })
+        });
+        report(play(frames, args.invert)?);
+        return Ok(());
+    }
+
+    let frames = list_txts(&args.txt_dir)?;
+    if frames.is_empty() {
+        anyhow::bail!("no .txt frames found in {}", args.txt_dir.display());
     }
 
-    execute!(out, LeaveAlternateScreen)?;
+    let frame_time = frame_time.unwrap_or(Duration::from_millis(1000 / 30));
+    let first = (start.as_secs_f64() / frame_time.as_secs_f64()) as usize;
+    let frames = frames.iter().skip(first).map(|path| {
+        let text = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+        Ok(AsciiFrame { text, delay: frame_time })
+    });
+    report(play(frames, args.invert)?);
     Ok(())
 }
@@ -1,11 +1,8 @@
 use anyhow::Result;
 use clap::Parser;
-use image::ImageReader;
-use rust_ascii::{
-    image_to_ascii, select_filter, select_ramp, ascii_to_html, HtmlConfig,
-    validate_image_args, validate_hex_color, print_conversion_summary
-};
+use rust_ascii::{image_to_grid, HtmlConfig, ConvertArgs, validate_hex_color, print_conversion_summary};
 use std::fs;
+use std::path::Path;
 
 // Runs the full workflow, converting an image to ASCII art and then to HTML
 
@@ -21,27 +18,14 @@ struct Args {
     #[arg(long, default_value = "ascii_art.html")]
     output: String,
     
-    /// Number of columns in the output (ignored for 1to1 mode)
-    #[arg(long, default_value_t = 120)]
-    cols: u32,
-    
-    /// Terminal cell aspect ratio (height/width, default: 2.0)
-    #[arg(long, default_value_t = 2.0)]
-    cell_aspect: f32,
-    
-    /// Resampling filter to use (nearest, triangle, lanczos3, pixel, 1to1)
-    #[arg(long, default_value = "triangle")]
-    resizer: String,
-    
-    /// Glyph ramp for mapping luminance to characters
-    #[arg(long, default_value = "basic")]
-    ramp: String,
+    #[command(flatten)]
+    convert: ConvertArgs,
     
     /// Font size in pixels
     #[arg(long, default_value_t = 1)]
     font_size: u32,
     
-    /// Background color (hex, e.g., 000000 for black)
+    /// Background color (hex, e.g., 000000 for black, or transparent)
     #[arg(long, default_value = "000000")]
     background: String,
     
@@ -59,30 +43,21 @@ fn main() -> Result<()> {
     let args = Args::parse();
 
     // Validate arguments
-    validate_image_args(args.cols, args.cell_aspect)?;
-    validate_hex_color(&args.background)?;
+    let config = args.convert.to_config(None, 120)?;
+    if args.background != "transparent" {
+        validate_hex_color(&args.background)?;
+    }
     validate_hex_color(&args.text_color)?;
 
     println!("Converting {} to ASCII art...", args.input);
 
-    // Load and decode image
-    let img = ImageReader::open(&args.input)
-        .map_err(|e| anyhow::anyhow!("failed to open {}: {e}", args.input))?
-        .decode()
-        .map_err(|e| anyhow::anyhow!("failed to decode {}: {e}", args.input))?;
-
-    // Select filter and ramp
-    let filter = select_filter(&args.resizer)?;
-    let ramp = select_ramp(&args.ramp)?;
+    // Load, decode and preprocess image
+    let img = args.convert.to_preprocess()?.open(Path::new(&args.input))?;
     
     // Convert to ASCII
-    let ascii = image_to_ascii(&img, args.cols, args.cell_aspect, filter, &args.resizer, ramp)?;
+    let grid = image_to_grid(&img, &config)?;
     
     // Generate HTML
-    let lines: Vec<&str> = ascii.lines().collect();
-    let max_width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
-    let height = lines.len();
-    
     let html_config = HtmlConfig {
         font_size: args.font_size,
         background_color: args.background,
@@ -90,7 +65,7 @@ fn main() -> Result<()> {
         font_family: args.font_family,
     };
     
-    let html = ascii_to_html(&ascii, html_config)?;
+    let html = grid.to_html(html_config)?;
     
     // Save HTML file
     fs::write(&args.output, html)
@@ -99,9 +74,9 @@ fn main() -> Result<()> {
     print_conversion_summary(
         &args.input,
         &args.output,
-        (max_width, height),
+        (grid.width() as usize, grid.rows as usize),
         args.font_size,
-        &args.resizer,
+        &args.convert.resizer,
     );
     
     Ok(())
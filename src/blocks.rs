@@ -0,0 +1,113 @@
+//! Block-element glyphs (half blocks, quadrants, sextants) and best-fit color selection
+
+/// Half blocks: bit 0 = top, bit 1 = bottom
+const HALF_BLOCKS: [char; 4] = [' ', '▀', '▄', '█'];
+
+/// Quadrants: bit 0 = top-left, 1 = top-right, 2 = bottom-left, 3 = bottom-right
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+/// Glyph for a half-block pattern (2 sub-pixels, top to bottom)
+pub fn half_block_char(pattern: u8) -> char {
+    HALF_BLOCKS[(pattern & 0b11) as usize]
+}
+
+/// Glyph for a quadrant pattern (2×2 sub-pixels, row-major)
+pub fn quadrant_char(pattern: u8) -> char {
+    QUADRANTS[(pattern & 0b1111) as usize]
+}
+
+/// Glyph for a sextant pattern (2×3 sub-pixels, row-major).
+/// Uses the Unicode 13 "Symbols for Legacy Computing" block, which skips the
+/// patterns that already exist as space, full block and left/right half blocks.
+pub fn sextant_char(pattern: u8) -> char {
+    match pattern & 0b11_1111 {
+        0 => ' ',
+        21 => '▌',
+        42 => '▐',
+        63 => '█',
+        p => {
+            let offset = p as u32 - 1 - (p > 21) as u32 - (p > 42) as u32;
+            char::from_u32(0x1FB00 + offset).unwrap_or('?')
+        }
+    }
+}
+
+/// Choose the sub-pixel pattern plus fg/bg color pair that best reproduces `pixels`.
+///
+/// Set bits of the returned pattern take the foreground color, clear bits the
+/// background. Each pattern and its complement fit equally well with the colors
+/// swapped, so only patterns with the first sub-pixel set are searched.
+pub fn best_fit(pixels: &[[u8; 3]]) -> (u8, [u8; 3], [u8; 3]) {
+    let n = pixels.len().min(8);
+    let full = ((1u16 << n) - 1) as u8;
+    let mean = |pattern: u8, want: bool| {
+        let mut sum = [0u32; 3];
+        let mut count = 0u32;
+        for (i, p) in pixels.iter().take(n).enumerate() {
+            if (pattern >> i & 1 == 1) == want {
+                for c in 0..3 {
+                    sum[c] += p[c] as u32;
+                }
+                count += 1;
+            }
+        }
+        (count > 0).then(|| sum.map(|s| ((s + count / 2) / count) as u8))
+    };
+    let error = |pattern: u8, fg: [u8; 3], bg: [u8; 3]| -> u32 {
+        pixels.iter().take(n).enumerate().map(|(i, p)| {
+            let target = if pattern >> i & 1 == 1 { fg } else { bg };
+            (0..3).map(|c| (p[c] as i32 - target[c] as i32).pow(2) as u32).sum::<u32>()
+        }).sum()
+    };
+
+    // Start from a solid cell; a uniform cell keeps fg == bg
+    let solid = mean(full, true).unwrap_or([0, 0, 0]);
+    let mut best = (full, solid, solid);
+    let mut best_err = error(full, solid, solid);
+
+    for pattern in (1..full).filter(|p| p & 1 == 1) {
+        if best_err == 0 {
+            break;
+        }
+        let (Some(fg), Some(bg)) = (mean(pattern, true), mean(pattern, false)) else { continue };
+        let err = error(pattern, fg, bg);
+        if err < best_err {
+            best = (pattern, fg, bg);
+            best_err = err;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sextant_char() {
+        assert_eq!(sextant_char(0), ' ');
+        assert_eq!(sextant_char(1), '\u{1FB00}');
+        assert_eq!(sextant_char(21), '▌');
+        assert_eq!(sextant_char(22), '\u{1FB14}');
+        assert_eq!(sextant_char(62), '\u{1FB3B}');
+        assert_eq!(sextant_char(63), '█');
+    }
+
+    #[test]
+    fn test_best_fit() {
+        let red = [255, 0, 0];
+        let blue = [0, 0, 255];
+
+        // Top red, bottom blue: upper half block, red on blue
+        assert_eq!(best_fit(&[red, blue]), (0b01, red, blue));
+
+        // Diagonal quadrant pattern
+        let (pattern, fg, bg) = best_fit(&[red, blue, blue, red]);
+        assert_eq!((quadrant_char(pattern), fg, bg), ('▚', red, blue));
+
+        // Uniform cells become a full block with matching colors
+        assert_eq!(best_fit(&[red; 4]), (0b1111, red, red));
+    }
+}
@@ -0,0 +1,36 @@
+//! Unicode braille patterns (U+2800–U+28FF) for 2×4 dot-per-cell rendering
+
+/// Bit for each dot, indexed by `[row][col]` in the 2-wide, 4-tall cell
+const DOT_BITS: [[u8; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+/// Build the braille character whose dots are set where `is_on(col, row)` is true
+pub fn braille_char(is_on: impl Fn(u32, u32) -> bool) -> char {
+    let mut bits = 0u8;
+    for (row, row_bits) in DOT_BITS.iter().enumerate() {
+        for (col, &bit) in row_bits.iter().enumerate() {
+            if is_on(col as u32, row as u32) {
+                bits |= bit;
+            }
+        }
+    }
+    // All 256 patterns are assigned, so this never falls back
+    char::from_u32(0x2800 + bits as u32).unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_braille_char() {
+        assert_eq!(braille_char(|_, _| false), '\u{2800}');
+        assert_eq!(braille_char(|_, _| true), '\u{28FF}');
+        assert_eq!(braille_char(|col, row| col == 0 && row == 0), '\u{2801}');
+        assert_eq!(braille_char(|col, row| col == 1 && row == 3), '\u{2880}');
+    }
+}
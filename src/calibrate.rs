@@ -0,0 +1,94 @@
+//! Automatic glyph ramp ordering and calibration from the bundled bitmap font
+
+use anyhow::{bail, Result};
+use crate::dither::nearest_level_lut;
+use crate::font::{coverage, glyph_bitmap};
+
+/// Glyphs whose ink coverage differs by less than this are near-duplicates
+/// (about one pixel of the 8×8 cell)
+const MIN_COVERAGE_STEP: f32 = 1.5 / 64.0;
+
+/// A ramp sorted light → dark by measured ink coverage
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibratedRamp {
+    /// Glyphs in ascending coverage order
+    pub glyphs: Vec<char>,
+    /// Ink coverage of each glyph (fraction of the cell, 0.0–1.0)
+    pub coverage: Vec<f32>,
+}
+
+impl CalibratedRamp {
+    /// Luminance (0–255) each glyph stands for, stretched so the lightest
+    /// glyph maps to 0 and the darkest to 255
+    pub fn levels(&self) -> Vec<f32> {
+        let min = self.coverage.first().copied().unwrap_or(0.0);
+        let max = self.coverage.last().copied().unwrap_or(0.0);
+        let span = max - min;
+        self.coverage
+            .iter()
+            .map(|&c| if span > 0.0 { (c - min) / span * 255.0 } else { 0.0 })
+            .collect()
+    }
+
+    /// Non-uniform luminance → glyph index lookup table. Each glyph covers the
+    /// band of luminance values closer to its own coverage than to its neighbours'.
+    pub fn lut(&self) -> [usize; 256] {
+        nearest_level_lut(&self.levels())
+    }
+}
+
+/// Rasterize each candidate glyph, measure its ink coverage, sort light → dark
+/// and drop near-duplicates (keeping the first candidate of each density)
+pub fn calibrate_ramp(chars: &str) -> Result<CalibratedRamp> {
+    let mut measured: Vec<(char, f32)> = Vec::new();
+    for c in chars.chars() {
+        if measured.iter().any(|&(seen, _)| seen == c) {
+            continue;
+        }
+        let Some(bitmap) = glyph_bitmap(c) else {
+            bail!("glyph {c:?} is not in the bundled font and cannot be calibrated");
+        };
+        measured.push((c, coverage(&bitmap)));
+    }
+    if measured.is_empty() {
+        bail!("ramp cannot be empty");
+    }
+
+    // Stable sort keeps the caller's order among equally dense glyphs
+    measured.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut ramp = CalibratedRamp { glyphs: Vec::new(), coverage: Vec::new() };
+    for (c, cov) in measured {
+        if ramp.coverage.last().is_some_and(|&last| cov - last < MIN_COVERAGE_STEP) {
+            continue;
+        }
+        ramp.glyphs.push(c);
+        ramp.coverage.push(cov);
+    }
+    Ok(ramp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibrate_sorts_and_dedups() {
+        // Out of order, with a repeat and two equally dense glyphs ('-' and ',')
+        let ramp = calibrate_ramp("@.@ -,:").unwrap();
+        assert_eq!(ramp.glyphs, vec![' ', '.', '-', ':', '@']);
+        assert!(ramp.coverage.windows(2).all(|w| w[0] < w[1]));
+
+        assert!(calibrate_ramp("").is_err());
+        assert!(calibrate_ramp("中").is_err());
+    }
+
+    #[test]
+    fn test_calibrated_lut() {
+        let ramp = calibrate_ramp(" ░▒▓█").unwrap();
+        let lut = ramp.lut();
+        assert_eq!(lut[0], 0);
+        assert_eq!(lut[128], 2);
+        assert_eq!(lut[255], 4);
+    }
+}
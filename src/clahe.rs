@@ -0,0 +1,207 @@
+//! Contrast-limited adaptive histogram equalization (CLAHE) of the sampled luminance grid
+
+use anyhow::{bail, Result};
+use image::GrayImage;
+
+/// CLAHE settings
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaheConfig {
+    /// Tile width in cells; tiles are as tall as they are wide on screen
+    pub tile_size: u32,
+    /// Histogram bins are clipped at this multiple of the mean bin height;
+    /// higher values allow more local contrast, values near 1.0 barely any
+    pub clip_limit: f32,
+    /// Precomputed tile histograms (e.g. smoothed across video frames);
+    /// `None` computes them from the image being converted
+    pub histograms: Option<TileHistograms>,
+}
+
+impl Default for ClaheConfig {
+    fn default() -> Self {
+        Self { tile_size: 16, clip_limit: 2.0, histograms: None }
+    }
+}
+
+impl ClaheConfig {
+    /// Check that the tile size and clip limit are usable
+    pub fn validate(&self) -> Result<()> {
+        if self.tile_size == 0 {
+            bail!("--clahe-tile must be positive");
+        }
+        if !(self.clip_limit >= 1.0 && self.clip_limit.is_finite()) {
+            bail!("--clahe-clip must be at least 1.0");
+        }
+        Ok(())
+    }
+}
+
+/// Normalized luminance histograms of a grid of tiles
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileHistograms {
+    width: u32,
+    height: u32,
+    tile_w: u32,
+    tile_h: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    /// One histogram per tile, row-major, each summing to 1.0
+    bins: Vec<[f32; 256]>,
+}
+
+impl TileHistograms {
+    /// Histogram each `tile_w`×`tile_h` tile of `gray`
+    pub fn compute(gray: &GrayImage, tile_w: u32, tile_h: u32) -> Self {
+        let mut histograms = Self::empty(gray.dimensions(), tile_w, tile_h);
+        histograms.add_rows(gray, 0);
+        histograms.normalize();
+        histograms
+    }
+
+    /// Empty histograms for a grid of `width`×`height`, to fill a band of rows
+    /// at a time with [`add_rows`](Self::add_rows) and then
+    /// [`normalize`](Self::normalize)
+    pub(crate) fn empty((width, height): (u32, u32), tile_w: u32, tile_h: u32) -> Self {
+        let (tile_w, tile_h) = (tile_w.clamp(1, width.max(1)), tile_h.clamp(1, height.max(1)));
+        let (tiles_x, tiles_y) = (width.div_ceil(tile_w), height.div_ceil(tile_h));
+        let bins = vec![[0.0f32; 256]; (tiles_x * tiles_y) as usize];
+        Self { width, height, tile_w, tile_h, tiles_x, tiles_y, bins }
+    }
+
+    /// Count the grid rows from `first` on held by `rows`
+    pub(crate) fn add_rows(&mut self, rows: &GrayImage, first: u32) {
+        for (x, y, p) in rows.enumerate_pixels() {
+            self.bins[(((y + first) / self.tile_h) * self.tiles_x + x / self.tile_w) as usize][p[0] as usize] += 1.0;
+        }
+    }
+
+    /// Scale each tile's histogram to sum to 1.0
+    pub(crate) fn normalize(&mut self) {
+        for hist in &mut self.bins {
+            let total: f32 = hist.iter().sum();
+            if total > 0.0 {
+                hist.iter_mut().for_each(|h| *h /= total);
+            }
+        }
+    }
+
+    /// Exponential moving average with the histograms of earlier frames:
+    /// keeps `smoothing` of `previous` and `1 - smoothing` of `self`.
+    /// Grids of a different shape are left untouched.
+    pub fn blend(&mut self, previous: &TileHistograms, smoothing: f32) {
+        if (self.width, self.height, self.tile_w, self.tile_h) != (previous.width, previous.height, previous.tile_w, previous.tile_h) {
+            return;
+        }
+        for (hist, prev) in self.bins.iter_mut().zip(&previous.bins) {
+            for (h, p) in hist.iter_mut().zip(prev) {
+                *h = smoothing * p + (1.0 - smoothing) * *h;
+            }
+        }
+    }
+
+    /// Whether these histograms were computed for a grid of this size
+    pub fn fits(&self, gray: &GrayImage) -> bool {
+        gray.dimensions() == (self.width, self.height)
+    }
+
+    /// Whether these histograms were computed for a grid of `width`×`height`
+    pub(crate) fn fits_size(&self, size: (u32, u32)) -> bool {
+        size == (self.width, self.height)
+    }
+
+    /// Equalize `gray` in place with the clipped tile histograms, bilinearly
+    /// interpolating the mappings of the four nearest tile centers
+    pub fn apply(&self, gray: &mut GrayImage, clip_limit: f32) {
+        self.mappings(clip_limit).apply(gray, 0);
+    }
+
+    /// The clipped mapping of each tile, to equalize a band of rows at a time
+    pub(crate) fn mappings(&self, clip_limit: f32) -> TileMappings {
+        let TileHistograms { tile_w, tile_h, tiles_x, tiles_y, .. } = *self;
+        let luts = self.bins.iter().map(|hist| clipped_lut(hist, clip_limit)).collect();
+        TileMappings { tile_w, tile_h, tiles_x, tiles_y, luts }
+    }
+}
+
+/// Clipped tile mappings from [`TileHistograms::mappings`]
+pub(crate) struct TileMappings {
+    tile_w: u32,
+    tile_h: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    luts: Vec<[f32; 256]>,
+}
+
+impl TileMappings {
+    /// Equalize the grid rows from `first` on held by `gray`
+    pub(crate) fn apply(&self, gray: &mut GrayImage, first: u32) {
+        let TileMappings { tile_w, tile_h, tiles_x, tiles_y, .. } = *self;
+        let tile_pos = |p: u32, size: u32, count: u32| {
+            let f = ((p as f32 + 0.5) / size as f32 - 0.5).clamp(0.0, (count - 1) as f32);
+            let i = f.floor() as u32;
+            (i, (i + 1).min(count - 1), f - i as f32)
+        };
+        for (x, y, p) in gray.enumerate_pixels_mut() {
+            let (x0, x1, wx) = tile_pos(x, tile_w, tiles_x);
+            let (y0, y1, wy) = tile_pos(y + first, tile_h, tiles_y);
+            let v = p[0] as usize;
+            let lut = |tx: u32, ty: u32| self.luts[(ty * tiles_x + tx) as usize][v];
+            let top = lut(x0, y0) * (1.0 - wx) + lut(x1, y0) * wx;
+            let bottom = lut(x0, y1) * (1.0 - wx) + lut(x1, y1) * wx;
+            p[0] = (top * (1.0 - wy) + bottom * wy).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Clip a normalized histogram at `clip_limit` times the mean bin height,
+/// spread the excess evenly over all bins and return the cumulative mapping
+fn clipped_lut(hist: &[f32; 256], clip_limit: f32) -> [f32; 256] {
+    let limit = clip_limit / 256.0;
+    let excess: f32 = hist.iter().map(|&h| (h - limit).max(0.0)).sum();
+    let mut lut = [0.0; 256];
+    let mut running = 0.0;
+    for (out, &h) in lut.iter_mut().zip(hist) {
+        running += h.min(limit) + excess / 256.0;
+        *out = running * 255.0;
+    }
+    lut
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn test_clahe_boosts_local_contrast() {
+        // Dark left half and bright right half, each with a faint texture
+        let gray = GrayImage::from_fn(32, 16, |x, y| {
+            let base = if x < 16 { 20 } else { 220 };
+            Luma([base + ((x + y) % 2) as u8 * 8])
+        });
+        let mut out = gray.clone();
+        TileHistograms::compute(&gray, 8, 8).apply(&mut out, 4.0);
+
+        let spread = |img: &GrayImage| img.get_pixel(1, 4)[0] as i32 - img.get_pixel(0, 4)[0] as i32;
+        assert!(spread(&out) > spread(&gray));
+        // Ordering within a tile is preserved
+        assert!(out.get_pixel(1, 4)[0] > out.get_pixel(0, 4)[0]);
+    }
+
+    #[test]
+    fn test_flat_tiles_stay_flat() {
+        // Clipping keeps a uniform area from being stretched to black or white
+        let gray = GrayImage::from_pixel(16, 16, Luma([128]));
+        let mut out = gray.clone();
+        TileHistograms::compute(&gray, 8, 8).apply(&mut out, 2.0);
+        assert!(out.pixels().all(|p| p[0].abs_diff(128) <= 2));
+    }
+
+    #[test]
+    fn test_blend() {
+        let dark = TileHistograms::compute(&GrayImage::from_pixel(8, 8, Luma([0])), 8, 8);
+        let mut bright = TileHistograms::compute(&GrayImage::from_pixel(8, 8, Luma([255])), 8, 8);
+        bright.blend(&dark, 0.75);
+        assert_eq!(bright.bins[0][0], 0.75);
+        assert_eq!(bright.bins[0][255], 0.25);
+    }
+}
@@ -1,12 +1,201 @@
 //! Shared CLI utilities and validation functions
 
 use anyhow::{Result, bail};
+use clap::Args;
+use crate::ansi::select_color_mode;
+use crate::clahe::ClaheConfig;
+use crate::dither::select_dither;
+use crate::edges::select_edge_mode;
+use crate::image_processing::{select_ramp_with_levels, select_render_mode, AsciiConfig};
+use crate::luminance::select_luma_model;
+use crate::preprocess::{parse_crop, Preprocess};
+use crate::sampler::select_resizer;
+use crate::sizing::{resolve_size, select_fit};
+use crate::tone::ToneConfig;
 
-/// Validate CLI arguments for image processing
-pub fn validate_image_args(cols: u32, cell_aspect: f32) -> Result<()> {
-    if cols == 0 {
+/// Sizing, preprocessing and conversion flags shared by the binaries, for
+/// `#[command(flatten)]`
+#[derive(Args, Debug, Clone)]
+pub struct ConvertArgs {
+    /// Number of columns in the output [default: the terminal width when printing to one, else 120; 160 in ascii-batch]
+    #[arg(long)]
+    pub cols: Option<u32>,
+
+    /// Number of rows in the output; with --cols, the image is fitted to both
+    #[arg(long)]
+    pub rows: Option<u32>,
+
+    /// How the image fits when both --cols and --rows are given (contain, cover, stretch)
+    #[arg(long, default_value = "contain")]
+    pub fit: String,
+
+    /// Crop x,y,width,height in pixels or percentages (e.g. 10%,0,80%,100%), after rotating and flipping
+    #[arg(long)]
+    pub crop: Option<String>,
+
+    /// Rotate the image clockwise by this many degrees; angles other than multiples of 90 leave transparent corners
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub rotate: f32,
+
+    /// Mirror the image left to right
+    #[arg(long)]
+    pub flip_h: bool,
+
+    /// Mirror the image top to bottom
+    #[arg(long)]
+    pub flip_v: bool,
+
+    /// Ignore the EXIF orientation instead of turning the image upright
+    #[arg(long)]
+    pub no_auto_orient: bool,
+
+    /// Terminal cell aspect ratio (height/width, default: 2.0)
+    #[arg(long, default_value_t = 2.0)]
+    pub cell_aspect: f32,
+
+    /// Resampling filter to use (nearest, triangle, lanczos3, pixel, 1to1, area, min, max, median)
+    #[arg(long, default_value = "triangle")]
+    pub resizer: String,
+
+    /// Glyph ramp for mapping luminance to characters (basic, classic, blocks, auto:<chars>, or custom)
+    #[arg(long, default_value = "basic")]
+    pub ramp: String,
+
+    /// Grayscale model (rec709, rec601, lightness, red, green, blue, max, min)
+    #[arg(long, default_value = "rec709")]
+    pub luma: String,
+
+    /// Resample in linear light instead of gamma-encoded sRGB
+    #[arg(long)]
+    pub linear: bool,
+
+    /// Color that transparent pixels are composited over (hex)
+    #[arg(long, default_value = "000000")]
+    pub matte: String,
+
+    /// Leave transparent cells empty instead of drawing the matte
+    #[arg(long)]
+    pub transparent: bool,
+
+    /// Color output (auto, none, 16, 256, truecolor); `--color` alone detects from COLORTERM/TERM
+    #[arg(long, num_args = 0..=1, default_value = "none", default_missing_value = "auto")]
+    pub color: String,
+
+    /// Dithering before ramp mapping (none, floyd-steinberg, atkinson, jarvis, bayer2, bayer4, bayer8, blue-noise)
+    #[arg(long, default_value = "none")]
+    pub dither: String,
+
+    /// Render mode (ramp, braille, half, quadrant, sextant, structural)
+    #[arg(long, default_value = "ramp")]
+    pub mode: String,
+
+    /// Edge glyphs along Sobel edges in ramp mode (off, overlay, only)
+    #[arg(long, default_value = "off")]
+    pub edges: String,
+
+    /// Edge strength needed for an edge glyph (0.0-1.0)
+    #[arg(long, default_value_t = 0.25)]
+    pub edge_threshold: f32,
+
+    /// Gamma correction; above 1.0 brightens midtones
+    #[arg(long, default_value_t = 1.0)]
+    pub gamma: f32,
+
+    /// Brightness offset (-1.0 to 1.0)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub brightness: f32,
+
+    /// Contrast multiplier around mid-gray
+    #[arg(long, default_value_t = 1.0)]
+    pub contrast: f32,
+
+    /// Invert luminance (for light backgrounds)
+    #[arg(long)]
+    pub invert: bool,
+
+    /// Stretch levels, clipping this percentage at each end; `--auto-levels` alone clips 1%
+    #[arg(long, num_args = 0..=1, default_missing_value = "1.0")]
+    pub auto_levels: Option<f32>,
+
+    /// Global histogram equalization
+    #[arg(long)]
+    pub equalize: bool,
+
+    /// Contrast-limited adaptive histogram equalization for local detail
+    #[arg(long)]
+    pub clahe: bool,
+
+    /// CLAHE tile width in cells
+    #[arg(long, default_value_t = 16)]
+    pub clahe_tile: u32,
+
+    /// CLAHE clip limit (multiple of the mean histogram bin; 1.0 = no boost)
+    #[arg(long, default_value_t = 2.0)]
+    pub clahe_clip: f32,
+}
+
+impl ConvertArgs {
+    /// Validate the flags and build the conversion settings. Without a
+    /// column or row count the output fits `terminal`, if given, or is
+    /// `default_cols` wide.
+    pub fn to_config(&self, terminal: Option<(u32, u32)>, default_cols: u32) -> Result<AsciiConfig> {
+        validate_image_args(self.cols, self.rows, self.cell_aspect)?;
+        let (cols, rows, fit) = resolve_size(self.cols, self.rows, select_fit(&self.fit)?, terminal, default_cols);
+        let (ramp, ramp_levels) = select_ramp_with_levels(&self.ramp)?;
+        Ok(AsciiConfig {
+            cols,
+            rows,
+            fit,
+            cell_aspect: self.cell_aspect,
+            resizer: select_resizer(&self.resizer)?,
+            luma: select_luma_model(&self.luma)?,
+            linear: self.linear,
+            ramp,
+            ramp_levels,
+            color: select_color_mode(&self.color)?,
+            dither: select_dither(&self.dither)?,
+            mode: select_render_mode(&self.mode)?,
+            edges: select_edge_mode(&self.edges)?,
+            edge_threshold: self.edge_threshold,
+            tone: ToneConfig {
+                gamma: self.gamma,
+                brightness: self.brightness,
+                contrast: self.contrast,
+                invert: self.invert,
+                auto_levels: self.auto_levels,
+                equalize: self.equalize,
+            },
+            clahe: self.clahe.then_some(ClaheConfig {
+                tile_size: self.clahe_tile,
+                clip_limit: self.clahe_clip,
+                histograms: None,
+            }),
+            matte: parse_hex_color(&self.matte)?,
+            transparent: self.transparent,
+        })
+    }
+
+    /// The orientation, rotation, flips and crop applied before conversion
+    pub fn to_preprocess(&self) -> Result<Preprocess> {
+        Ok(Preprocess {
+            auto_orient: !self.no_auto_orient,
+            rotate: self.rotate,
+            flip_horizontal: self.flip_h,
+            flip_vertical: self.flip_v,
+            crop: self.crop.as_deref().map(parse_crop).transpose()?,
+        })
+    }
+}
+
+/// Validate CLI arguments for image processing; `None` sizes are left to
+/// [`resolve_size`](crate::sizing::resolve_size)
+pub fn validate_image_args(cols: Option<u32>, rows: Option<u32>, cell_aspect: f32) -> Result<()> {
+    if cols == Some(0) {
         bail!("--cols must be positive");
     }
+    if rows == Some(0) {
+        bail!("--rows must be positive");
+    }
     if !(0.5..=5.0).contains(&cell_aspect) {
         bail!("--cell-aspect should be between 0.5 and 5.0");
     }
@@ -18,11 +207,39 @@ pub fn validate_hex_color(hex: &str) -> Result<()> {
     if hex.len() != 6 {
         bail!("Color must be 6 hex digits (e.g., ffffff)");
     }
-    u32::from_str_radix(hex, 16)
-        .map_err(|_| anyhow::anyhow!("Invalid hex color: {}", hex))?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("Invalid hex color: {}", hex);
+    }
     Ok(())
 }
 
+/// Parse a 6-digit hex color into RGB
+pub fn parse_hex_color(hex: &str) -> Result<[u8; 3]> {
+    validate_hex_color(hex)?;
+    let value = u32::from_str_radix(hex, 16)?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// Parse a byte size such as `512M`, `1.5G`, `800k` or `4096` (binary units)
+pub fn parse_byte_size(size: &str) -> Result<u64> {
+    let trimmed = size.trim();
+    let digits = trimmed.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &trimmed[digits.len()..];
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => bail!("Invalid size unit in {size:?} (use K, M, G or T)"),
+    };
+    let value: f64 = digits.trim().parse().map_err(|_| anyhow::anyhow!("Invalid size: {size:?}"))?;
+    if !(value.is_finite() && value > 0.0) {
+        bail!("Size must be positive: {size:?}");
+    }
+    Ok((value * multiplier as f64) as u64)
+}
+
 /// Print conversion summary
 pub fn print_conversion_summary(
     input_path: &str,
@@ -47,10 +264,12 @@ mod tests {
 
     #[test]
     fn test_validate_image_args() {
-        assert!(validate_image_args(120, 2.0).is_ok());
-        assert!(validate_image_args(0, 2.0).is_err());
-        assert!(validate_image_args(120, 0.1).is_err());
-        assert!(validate_image_args(120, 10.0).is_err());
+        assert!(validate_image_args(Some(120), None, 2.0).is_ok());
+        assert!(validate_image_args(None, Some(40), 2.0).is_ok());
+        assert!(validate_image_args(Some(0), None, 2.0).is_err());
+        assert!(validate_image_args(Some(120), Some(0), 2.0).is_err());
+        assert!(validate_image_args(Some(120), None, 0.1).is_err());
+        assert!(validate_image_args(Some(120), None, 10.0).is_err());
     }
 
     #[test]
@@ -61,4 +280,37 @@ mod tests {
         assert!(validate_hex_color("gggggg").is_err());
         assert!(validate_hex_color("ffff").is_err());
     }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("ff8000").unwrap(), [255, 128, 0]);
+        assert!(parse_hex_color("+fffff").is_err());
+    }
+
+    #[test]
+    fn test_convert_args() {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            convert: ConvertArgs,
+        }
+        let args = <Cli as clap::Parser>::parse_from(["test", "--rows", "30", "--mode", "braille", "--flip-h", "--auto-levels"]).convert;
+        let config = args.to_config(Some((100, 40)), 120).unwrap();
+        assert_eq!((config.cols, config.rows, config.mode), (120, Some(30), crate::RenderMode::Braille));
+        assert_eq!(config.tone.auto_levels, Some(1.0));
+        assert!(args.to_preprocess().unwrap().flip_horizontal);
+
+        let args = <Cli as clap::Parser>::parse_from(["test", "--cols", "0"]).convert;
+        assert!(args.to_config(None, 120).is_err());
+    }
+
+    #[test]
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("4096").unwrap(), 4096);
+        assert_eq!(parse_byte_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_byte_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_byte_size("800k").unwrap(), 800 << 10);
+        assert!(parse_byte_size("0").is_err());
+        assert!(parse_byte_size("12X").is_err());
+    }
 }
@@ -0,0 +1,257 @@
+//! Keyframe and delta encoding of cell grids: a keyframe stores every cell,
+//! a delta only the runs of cells that changed since the previous frame
+
+use anyhow::{bail, ensure, Context, Result};
+use crate::ansi::AnsiColor;
+use crate::grid::{AsciiGrid, Cell, GridMetadata};
+
+/// Unchanged cells between two changed runs that are stored (or redrawn)
+/// anyway, since a run header costs more than a few cells
+const MERGE_GAP: u32 = 2;
+
+const HAS_FG: u8 = 1;
+const FG_RGB: u8 = 2;
+const HAS_BG: u8 = 4;
+const BG_RGB: u8 = 8;
+const HAS_ALPHA: u8 = 16;
+
+/// A horizontal run of changed cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub row: u32,
+    pub col: u32,
+    /// Number of cells
+    pub len: u32,
+}
+
+/// Runs of cells that differ between two grids of the same size; runs at
+/// most [`MERGE_GAP`] cells apart are merged
+pub fn changed_runs(prev: &AsciiGrid, next: &AsciiGrid) -> Vec<Run> {
+    let mut runs = Vec::new();
+    for (row, (a, b)) in prev.rows().zip(next.rows()).enumerate() {
+        push_row_runs(&mut runs, row as u32, a, b, None);
+    }
+    runs
+}
+
+/// Append the changed runs of one row to `runs`. Rows of different lengths
+/// are compared as if the shorter one were padded with `pad`.
+pub(crate) fn push_row_runs(runs: &mut Vec<Run>, row: u32, prev: &[Cell], next: &[Cell], pad: Option<&Cell>) {
+    let len = match pad {
+        Some(_) => prev.len().max(next.len()),
+        None => prev.len().min(next.len()),
+    };
+    let mut current: Option<Run> = None;
+    for col in 0..len {
+        if prev.get(col).or(pad) == next.get(col).or(pad) {
+            continue;
+        }
+        let col = col as u32;
+        match &mut current {
+            Some(run) if col - (run.col + run.len) <= MERGE_GAP => run.len = col + 1 - run.col,
+            _ => runs.extend(current.replace(Run { row, col, len: 1 })),
+        }
+    }
+    runs.extend(current);
+}
+
+/// Every cell of `grid`: its size, then the cells in row-major order
+pub fn encode_keyframe(grid: &AsciiGrid) -> Vec<u8> {
+    let mut out = Vec::with_capacity(grid.cells.len() * 2);
+    for value in [grid.cols, grid.rows, grid.cell_width] {
+        push_varint(&mut out, value);
+    }
+    push_cells(&mut out, &grid.cells);
+    out
+}
+
+/// The runs of `next` that differ from `prev`, then their cells, or `None`
+/// if the grid size changed and a keyframe is needed
+pub fn encode_delta(prev: &AsciiGrid, next: &AsciiGrid) -> Option<Vec<u8>> {
+    if (prev.cols, prev.rows, prev.cell_width) != (next.cols, next.rows, next.cell_width) {
+        return None;
+    }
+    let runs = changed_runs(prev, next);
+    let mut out = Vec::new();
+    let mut cells = Vec::new();
+    push_varint(&mut out, runs.len() as u32);
+    for run in runs {
+        for value in [run.row, run.col, run.len] {
+            push_varint(&mut out, value);
+        }
+        let start = (run.row * next.cols + run.col) as usize;
+        cells.extend_from_slice(&next.cells[start..start + run.len as usize]);
+    }
+    push_cells(&mut out, &cells);
+    Some(out)
+}
+
+/// Rebuild a grid from [`encode_keyframe`] output
+pub fn decode_keyframe(data: &[u8], metadata: GridMetadata) -> Result<AsciiGrid> {
+    let mut bytes = Bytes { data, pos: 0 };
+    let (cols, rows, cell_width) = (bytes.varint()?, bytes.varint()?, bytes.varint()?);
+    let count = cols as usize * rows as usize;
+    // Every cell takes at least two bytes: its glyph and flags
+    ensure!(count <= data.len() / 2, "keyframe is truncated");
+    let cells = bytes.cells(count)?;
+    ensure!(bytes.pos == data.len(), "keyframe has trailing data");
+    Ok(AsciiGrid { cols, rows, cell_width, cells, metadata })
+}
+
+/// Apply [`encode_delta`] output to the previous frame's grid
+pub fn apply_delta(grid: &mut AsciiGrid, data: &[u8]) -> Result<()> {
+    let mut bytes = Bytes { data, pos: 0 };
+    let count = bytes.varint()?;
+    ensure!(count as usize <= data.len() / 3, "delta is truncated");
+    let mut runs = Vec::with_capacity(count as usize);
+    let mut total = 0;
+    for _ in 0..count {
+        let run = Run { row: bytes.varint()?, col: bytes.varint()?, len: bytes.varint()? };
+        ensure!(
+            run.row < grid.rows && run.col.checked_add(run.len).is_some_and(|end| end <= grid.cols),
+            "delta run outside the grid"
+        );
+        total += run.len as usize;
+        runs.push(run);
+    }
+    let mut cells = bytes.cells(total)?.into_iter();
+    ensure!(bytes.pos == data.len(), "delta has trailing data");
+    for run in runs {
+        let start = (run.row * grid.cols + run.col) as usize;
+        grid.cells[start..start + run.len as usize].iter_mut().for_each(|cell| *cell = cells.next().expect("counted"));
+    }
+    Ok(())
+}
+
+/// The glyphs as a length-prefixed UTF-8 string, then each cell's flags,
+/// colors and alpha; keeping glyphs together helps them compress
+fn push_cells(out: &mut Vec<u8>, cells: &[Cell]) {
+    let glyphs: String = cells.iter().map(|cell| cell.glyph).collect();
+    push_varint(out, glyphs.len() as u32);
+    out.extend_from_slice(glyphs.as_bytes());
+    for cell in cells {
+        let flag = |color: Option<AnsiColor>, has: u8, rgb: u8| match color {
+            Some(AnsiColor::Indexed(_)) => has,
+            Some(AnsiColor::Rgb(_)) => has | rgb,
+            None => 0,
+        };
+        out.push(flag(cell.fg, HAS_FG, FG_RGB) | flag(cell.bg, HAS_BG, BG_RGB) | cell.alpha.map_or(0, |_| HAS_ALPHA));
+        for color in [cell.fg, cell.bg].into_iter().flatten() {
+            match color {
+                AnsiColor::Indexed(i) => out.push(i),
+                AnsiColor::Rgb(rgb) => out.extend_from_slice(&rgb),
+            }
+        }
+        out.extend(cell.alpha);
+    }
+}
+
+/// LEB128: seven bits per byte, low bits first
+fn push_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads encoded values, failing on truncated data
+struct Bytes<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self.pos.checked_add(n).and_then(|end| self.data.get(self.pos..end)).context("frame data is truncated")?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u32> {
+        let mut value = 0u64;
+        for shift in (0..35).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).context("varint overflows");
+            }
+        }
+        bail!("varint is too long")
+    }
+
+    fn color(&mut self, rgb: bool) -> Result<AnsiColor> {
+        Ok(match rgb {
+            true => AnsiColor::Rgb([self.u8()?, self.u8()?, self.u8()?]),
+            false => AnsiColor::Indexed(self.u8()?),
+        })
+    }
+
+    fn cells(&mut self, count: usize) -> Result<Vec<Cell>> {
+        let len = self.varint()? as usize;
+        let glyphs = std::str::from_utf8(self.take(len)?).context("glyphs are not UTF-8")?;
+        ensure!(glyphs.chars().count() == count, "frame has the wrong number of glyphs");
+        glyphs
+            .chars()
+            .map(|glyph| {
+                let flags = self.u8()?;
+                ensure!(flags < HAS_ALPHA << 1, "unknown cell flags {flags:#x}");
+                let fg = if flags & HAS_FG != 0 { Some(self.color(flags & FG_RGB != 0)?) } else { None };
+                let bg = if flags & HAS_BG != 0 { Some(self.color(flags & BG_RGB != 0)?) } else { None };
+                let alpha = if flags & HAS_ALPHA != 0 { Some(self.u8()?) } else { None };
+                Ok(Cell { glyph, fg, bg, alpha })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi::ColorMode;
+    use crate::image_processing::RenderMode;
+
+    fn grid(text: &str, cols: u32) -> AsciiGrid {
+        let cells: Vec<Cell> = text.chars().map(|glyph| Cell { glyph, fg: None, bg: None, alpha: None }).collect();
+        let rows = cells.len() as u32 / cols;
+        let metadata = GridMetadata { source_size: (cols, rows), mode: RenderMode::Ramp, color: ColorMode::Truecolor };
+        AsciiGrid { cols, rows, cell_width: 1, cells, metadata }
+    }
+
+    #[test]
+    fn test_changed_runs() {
+        let prev = grid("..........####", 7);
+        let next = grid("x.x...x...#$#$", 7);
+        // Cells 0 and 2 merge across the one-cell gap; cell 6 is three away
+        assert_eq!(changed_runs(&prev, &next), [
+            Run { row: 0, col: 0, len: 3 },
+            Run { row: 0, col: 6, len: 1 },
+            Run { row: 1, col: 4, len: 3 },
+        ]);
+        assert!(changed_runs(&prev, &prev).is_empty());
+    }
+
+    #[test]
+    fn test_keyframe_and_delta_round_trip() {
+        let mut first = grid("abcdef", 3);
+        first.cells[1].fg = Some(AnsiColor::Rgb([1, 2, 3]));
+        first.cells[4] = Cell { glyph: '█', fg: Some(AnsiColor::Indexed(9)), bg: Some(AnsiColor::Indexed(0)), alpha: Some(7) };
+        let mut second = first.clone();
+        second.cells[5].glyph = 'Z';
+
+        let mut decoded = decode_keyframe(&encode_keyframe(&first), first.metadata).unwrap();
+        assert_eq!(decoded, first);
+        let delta = encode_delta(&first, &second).unwrap();
+        apply_delta(&mut decoded, &delta).unwrap();
+        assert_eq!(decoded, second);
+        // One run of one plain cell: count, run, glyph length, glyph, flags
+        assert_eq!(delta, [1, 1, 2, 1, 1, b'Z', 0]);
+
+        assert!(encode_delta(&first, &grid("abcdef", 2)).is_none());
+        assert!(apply_delta(&mut decoded, &delta[..delta.len() - 1]).is_err());
+    }
+}
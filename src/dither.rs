@@ -0,0 +1,389 @@
+//! Dithering stage for mapping luminance onto a limited number of glyph levels
+
+use anyhow::Result;
+use image::GrayImage;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Dithering algorithm applied before ramp mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    /// Plain quantization, no dithering
+    #[default]
+    None,
+    /// Error diffusion to 4 neighbours (7/16, 3/16, 5/16, 1/16)
+    FloydSteinberg,
+    /// Error diffusion of 3/4 of the error to 6 neighbours, keeps contrast high
+    Atkinson,
+    /// Jarvis–Judice–Ninke error diffusion to 12 neighbours
+    JarvisJudiceNinke,
+    /// Ordered dithering with an N×N Bayer threshold matrix (N = 2, 4 or 8)
+    Bayer(u32),
+    /// Ordered dithering with a 32×32 blue-noise threshold map
+    BlueNoise,
+}
+
+impl Dither {
+    /// Ordered dithers use a fixed threshold per position, so they stay stable
+    /// between video frames where error diffusion would shimmer
+    pub fn is_ordered(self) -> bool {
+        matches!(self, Dither::Bayer(_) | Dither::BlueNoise)
+    }
+}
+
+/// Select the dithering algorithm based on its CLI name
+pub fn select_dither(name: &str) -> Result<Dither> {
+    Ok(match name {
+        "none" => Dither::None,
+        "floyd-steinberg" | "fs" => Dither::FloydSteinberg,
+        "atkinson" => Dither::Atkinson,
+        "jarvis" | "jjn" => Dither::JarvisJudiceNinke,
+        "bayer2" => Dither::Bayer(2),
+        "bayer" | "bayer4" => Dither::Bayer(4),
+        "bayer8" => Dither::Bayer(8),
+        "blue-noise" => Dither::BlueNoise,
+        other => anyhow::bail!(
+            "unknown dither: {other}. Available options: none, floyd-steinberg, atkinson, jarvis, bayer2, bayer4, bayer8, blue-noise"
+        ),
+    })
+}
+
+/// Error diffusion kernels as (dx, dy, weight) with a common divisor
+const FLOYD_STEINBERG: (&[(i32, i32, f32)], f32) = (
+    &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)],
+    16.0,
+);
+const ATKINSON: (&[(i32, i32, f32)], f32) = (
+    &[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)],
+    8.0,
+);
+const JARVIS_JUDICE_NINKE: (&[(i32, i32, f32)], f32) = (
+    &[
+        (1, 0, 7.0), (2, 0, 5.0),
+        (-2, 1, 3.0), (-1, 1, 5.0), (0, 1, 7.0), (1, 1, 5.0), (2, 1, 3.0),
+        (-2, 2, 1.0), (-1, 2, 3.0), (0, 2, 5.0), (1, 2, 3.0), (2, 2, 1.0),
+    ],
+    48.0,
+);
+
+/// Quantize a grayscale buffer to `levels` evenly spaced steps
+/// (row-major indices in `0..levels`)
+pub fn quantize(gray: &GrayImage, levels: usize, dither: Dither) -> Vec<usize> {
+    Quantizer::even(levels, dither, gray.dimensions()).rows(gray, 0, 0..gray.height())
+}
+
+/// Quantize a grayscale buffer to arbitrary, ascending luminance `levels`
+/// (0–255), returning row-major indices into `levels`. Without dithering each
+/// pixel takes the nearest level, so every level owns the band around it.
+pub fn quantize_levels(gray: &GrayImage, levels: &[f32], dither: Dither) -> Vec<usize> {
+    Quantizer::new(levels, dither, gray.dimensions()).rows(gray, 0, 0..gray.height())
+}
+
+/// Quantizes a grid a band of rows at a time, with the same result as
+/// quantizing it whole: error diffusion keeps the error spread onto rows it
+/// has not reached yet
+pub(crate) struct Quantizer {
+    levels: Vec<f32>,
+    dither: Dither,
+    /// Plain quantization to evenly spaced levels by integer division
+    even: bool,
+    /// Ordered dither threshold map and the period it repeats with
+    thresholds: (Vec<f32>, u32),
+    width: u32,
+    height: u32,
+    /// First row not quantized yet
+    next: u32,
+    /// Values of the rows from `next` on, plus the error diffused onto them
+    pending: Vec<f32>,
+}
+
+impl Quantizer {
+    /// Quantize a grid of `size` to `levels` evenly spaced steps, as [`quantize`] does
+    pub(crate) fn even(levels: usize, dither: Dither, size: (u32, u32)) -> Self {
+        let max = levels.saturating_sub(1);
+        let step = if max == 0 { 0.0 } else { 255.0 / max as f32 };
+        let values: Vec<f32> = (0..=max).map(|i| i as f32 * step).collect();
+        Self { even: dither == Dither::None, ..Self::new(&values, dither, size) }
+    }
+
+    /// Quantize a grid of `size` to `levels`, as [`quantize_levels`] does
+    pub(crate) fn new(levels: &[f32], dither: Dither, (width, height): (u32, u32)) -> Self {
+        let thresholds = match dither {
+            Dither::Bayer(n) => (bayer_matrix(n), n.max(1)),
+            Dither::BlueNoise => (blue_noise().to_vec(), BLUE_NOISE_SIZE),
+            _ => (Vec::new(), 1),
+        };
+        Self { levels: levels.to_vec(), dither, even: false, thresholds, width, height, next: 0, pending: Vec::new() }
+    }
+
+    /// Indices of the grid rows `rows`, row-major. `gray` holds the grid rows
+    /// from `top` on: `rows` and, for error diffusion, the two after them.
+    /// Error diffusion takes the rows in order.
+    pub(crate) fn rows(&mut self, gray: &GrayImage, top: u32, rows: Range<u32>) -> Vec<usize> {
+        let width = self.width;
+        let band = (rows.start..rows.end).flat_map(move |y| (0..width).map(move |x| (x, y)));
+        let value = |x: u32, y: u32| gray.get_pixel(x, y - top)[0];
+        match self.dither {
+            _ if self.even => {
+                let max = self.levels.len().saturating_sub(1);
+                band.map(|(x, y)| ((value(x, y) as usize * max) / 255).min(max)).collect()
+            }
+            Dither::None => {
+                let lut = nearest_level_lut(&self.levels);
+                band.map(|(x, y)| lut[value(x, y) as usize]).collect()
+            }
+            Dither::FloydSteinberg => self.diffuse(gray, top, rows, FLOYD_STEINBERG),
+            Dither::Atkinson => self.diffuse(gray, top, rows, ATKINSON),
+            Dither::JarvisJudiceNinke => self.diffuse(gray, top, rows, JARVIS_JUDICE_NINKE),
+            Dither::Bayer(_) | Dither::BlueNoise => {
+                let (map, n) = (&self.thresholds.0, self.thresholds.1);
+                band.map(|(x, y)| ordered(&self.levels, value(x, y), map[((y % n) * n + x % n) as usize])).collect()
+            }
+        }
+    }
+
+    /// Serpentine error diffusion with the given kernel
+    fn diffuse(&mut self, gray: &GrayImage, top: u32, rows: Range<u32>, (kernel, divisor): (&[(i32, i32, f32)], f32)) -> Vec<usize> {
+        assert_eq!(rows.start, self.next, "error diffusion takes the rows in order");
+        // Take in the rows up to the furthest one the kernel reaches
+        let seen = self.next + self.pending.len() as u32 / self.width.max(1);
+        for y in seen.max(top)..(top + gray.height()).min(rows.end + 2) {
+            self.pending.extend((0..self.width).map(|x| gray.get_pixel(x, y - top)[0] as f32));
+        }
+
+        let (w, h) = (self.width as i32, self.height as i32);
+        let first = rows.start as i32;
+        let values = &mut self.pending;
+        let mut out = vec![0; rows.len() * self.width as usize];
+        for y in first..rows.end as i32 {
+            // Alternate direction each row to avoid directional worm artifacts
+            let reverse = y % 2 == 1;
+            for i in 0..w {
+                let x = if reverse { w - 1 - i } else { i };
+                let pos = ((y - first) * w + x) as usize;
+                let v = values[pos];
+                let idx = nearest_level(&self.levels, v);
+                out[pos] = idx;
+
+                let err = v - self.levels.get(idx).copied().unwrap_or(0.0);
+                for &(dx, dy, weight) in kernel {
+                    let nx = if reverse { x - dx } else { x + dx };
+                    let ny = y + dy;
+                    if nx >= 0 && nx < w && ny < h {
+                        values[((ny - first) * w + nx) as usize] += err * weight / divisor;
+                    }
+                }
+            }
+        }
+        values.drain(..out.len());
+        self.next = rows.end;
+        out
+    }
+}
+
+/// Lookup table from luminance (0–255) to the index of the nearest level
+pub fn nearest_level_lut(levels: &[f32]) -> [usize; 256] {
+    let mut lut = [0; 256];
+    for (v, slot) in lut.iter_mut().enumerate() {
+        *slot = nearest_level(levels, v as f32);
+    }
+    lut
+}
+
+/// Index of the level closest to `v` (levels are ascending)
+fn nearest_level(levels: &[f32], v: f32) -> usize {
+    if levels.is_empty() {
+        return 0;
+    }
+    let hi = levels.partition_point(|&l| l < v).min(levels.len().saturating_sub(1));
+    if hi > 0 && (v - levels[hi - 1]) <= (levels[hi] - v) { hi - 1 } else { hi }
+}
+
+/// Ordered dithering: between the two levels bracketing a value, take the
+/// upper one when the value's position in the gap exceeds the threshold
+fn ordered(levels: &[f32], v: u8, threshold: f32) -> usize {
+    let max = levels.len().saturating_sub(1);
+    let v = v as f32;
+    let hi = levels.partition_point(|&l| l <= v).min(max);
+    if hi == 0 || levels[hi] <= v {
+        return hi;
+    }
+    let lo = hi - 1;
+    let frac = (v - levels[lo]) / (levels[hi] - levels[lo]);
+    if frac > threshold { hi } else { lo }
+}
+
+/// Normalized N×N Bayer threshold matrix with values in [0, 1)
+fn bayer_matrix(n: u32) -> Vec<f32> {
+    let n = n.max(1).next_power_of_two();
+    let mut m = vec![0u32];
+    let mut size = 1;
+    while size < n {
+        let mut next = vec![0u32; (size * size * 4) as usize];
+        for y in 0..size {
+            for x in 0..size {
+                let v = 4 * m[(y * size + x) as usize];
+                let s2 = size * 2;
+                next[(y * s2 + x) as usize] = v;
+                next[(y * s2 + x + size) as usize] = v + 2;
+                next[((y + size) * s2 + x) as usize] = v + 3;
+                next[((y + size) * s2 + x + size) as usize] = v + 1;
+            }
+        }
+        m = next;
+        size *= 2;
+    }
+    let count = (n * n) as f32;
+    m.into_iter().map(|v| (v as f32 + 0.5) / count).collect()
+}
+
+const BLUE_NOISE_SIZE: u32 = 32;
+
+/// Blue-noise threshold map generated once with the void-and-cluster method
+fn blue_noise() -> &'static [f32] {
+    static MAP: OnceLock<Vec<f32>> = OnceLock::new();
+    MAP.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE as usize))
+}
+
+/// Ulichney's void-and-cluster algorithm on a toroidal `size`×`size` grid
+fn void_and_cluster(size: usize) -> Vec<f32> {
+    let n = size * size;
+    let sigma2 = 2.0 * 1.5f32 * 1.5;
+
+    // Toroidal gaussian kernel indexed by (dx, dy)
+    let kernel: Vec<f32> = (0..n)
+        .map(|i| {
+            let (dx, dy) = (i % size, i / size);
+            let dx = dx.min(size - dx) as f32;
+            let dy = dy.min(size - dy) as f32;
+            (-(dx * dx + dy * dy) / sigma2).exp()
+        })
+        .collect();
+    let update = |energy: &mut [f32], p: usize, sign: f32| {
+        let (px, py) = (p % size, p / size);
+        for (q, e) in energy.iter_mut().enumerate() {
+            let dx = (q % size + size - px) % size;
+            let dy = (q / size + size - py) % size;
+            *e += sign * kernel[dy * size + dx];
+        }
+    };
+    let argmax = |energy: &[f32], pattern: &[bool], want: bool| {
+        (0..n).filter(|&i| pattern[i] == want)
+            .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap_or(0)
+    };
+    let argmin = |energy: &[f32], pattern: &[bool], want: bool| {
+        (0..n).filter(|&i| pattern[i] == want)
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap_or(0)
+    };
+
+    // Deterministic initial pattern with ~10% of the points set
+    let mut pattern = vec![false; n];
+    let mut seed: u32 = 0x9E37_79B9;
+    let ones = n / 10;
+    let mut placed = 0;
+    while placed < ones {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let p = seed as usize % n;
+        if !pattern[p] {
+            pattern[p] = true;
+            placed += 1;
+        }
+    }
+    let mut energy = vec![0.0f32; n];
+    for p in (0..n).filter(|&p| pattern[p]) {
+        update(&mut energy, p, 1.0);
+    }
+
+    // Relax: move the tightest cluster into the largest void until stable
+    for _ in 0..n {
+        let cluster = argmax(&energy, &pattern, true);
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+        let void = argmin(&energy, &pattern, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0usize; n];
+
+    // Phase 1: rank the initial points by removing tightest clusters
+    let (mut p1, mut e1) = (pattern.clone(), energy.clone());
+    for r in (0..ones).rev() {
+        let cluster = argmax(&e1, &p1, true);
+        p1[cluster] = false;
+        update(&mut e1, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    // Phase 2: fill the largest voids until every position is ranked
+    for r in ones..n {
+        let void = argmin(&energy, &pattern, false);
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.into_iter().map(|r| (r as f32 + 0.5) / n as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_dither() {
+        assert_eq!(select_dither("fs").unwrap(), Dither::FloydSteinberg);
+        assert_eq!(select_dither("bayer8").unwrap(), Dither::Bayer(8));
+        assert!(select_dither("bayer8").unwrap().is_ordered());
+        assert!(select_dither("random").is_err());
+    }
+
+    #[test]
+    fn test_bayer_matrix() {
+        let m = bayer_matrix(2);
+        assert_eq!(m, vec![0.125, 0.625, 0.875, 0.375]);
+    }
+
+    #[test]
+    fn test_dither_preserves_mean() {
+        // A flat mid-gray at two levels should come out roughly half on, half off
+        let gray = GrayImage::from_pixel(32, 32, image::Luma([128]));
+        for dither in [Dither::FloydSteinberg, Dither::Atkinson, Dither::JarvisJudiceNinke, Dither::Bayer(4), Dither::BlueNoise] {
+            let on = quantize(&gray, 2, dither).iter().filter(|&&i| i == 1).count();
+            assert!((400..=624).contains(&on), "{dither:?} produced {on} of 1024");
+        }
+        assert!(quantize(&gray, 2, Dither::None).iter().all(|&i| i == 0));
+    }
+
+    #[test]
+    fn test_quantize_levels() {
+        // Non-uniform levels: each pixel goes to the nearest one
+        let gray = GrayImage::from_fn(4, 1, |x, _| image::Luma([[0, 20, 60, 255][x as usize]]));
+        assert_eq!(quantize_levels(&gray, &[0.0, 50.0, 255.0], Dither::None), vec![0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_quantize_in_bands() {
+        // Bands of three rows, each with the two rows after it, match the whole grid
+        let gray = GrayImage::from_fn(9, 10, |x, y| image::Luma([(x * 29 + y * 13) as u8]));
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::JarvisJudiceNinke, Dither::Bayer(4)] {
+            let mut quantizer = Quantizer::even(3, dither, gray.dimensions());
+            let banded: Vec<usize> = (0..10)
+                .step_by(3)
+                .flat_map(|y| {
+                    let rows = y..(y + 3).min(10);
+                    let band = image::imageops::crop_imm(&gray, 0, y, 9, (rows.end + 2).min(10) - y).to_image();
+                    quantizer.rows(&band, y, rows)
+                })
+                .collect();
+            assert_eq!(banded, quantize(&gray, 3, dither), "{dither:?}");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Sobel edge detection for drawing direction-appropriate line glyphs
+
+use anyhow::Result;
+use image::GrayImage;
+
+/// How detected edges are combined with the ramp output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EdgeMode {
+    /// No edge detection
+    #[default]
+    Off,
+    /// Edge glyphs replace ramp glyphs where an edge is found
+    Overlay,
+    /// Only edge glyphs are drawn, everything else is blank
+    Only,
+}
+
+/// Select the edge mode based on its CLI name
+pub fn select_edge_mode(name: &str) -> Result<EdgeMode> {
+    Ok(match name {
+        "off" => EdgeMode::Off,
+        "overlay" => EdgeMode::Overlay,
+        "only" => EdgeMode::Only,
+        other => anyhow::bail!("unknown edge mode: {other}. Available options: off, overlay, only"),
+    })
+}
+
+/// Detect edges in a grid of cells and return a line glyph for each edge cell.
+///
+/// Sobel gradients are normalized so a full black-to-white step is 1.0,
+/// thinned with non-maximum suppression along the gradient direction, then
+/// kept where the magnitude is at least `threshold`. `cell_aspect` corrects
+/// the vertical gradient for cells being taller than they are wide, so that
+/// a 45° line in the image gets `/` or `\` rather than a steeper glyph.
+pub fn detect_edges(gray: &GrayImage, threshold: f32, cell_aspect: f32) -> Vec<Option<char>> {
+    let (w, h) = (gray.width() as i32, gray.height() as i32);
+    let px = |x: i32, y: i32| gray.get_pixel(x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32)[0] as f32;
+
+    let mut gradients = Vec::with_capacity((w * h) as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let gx = (px(x + 1, y - 1) + 2.0 * px(x + 1, y) + px(x + 1, y + 1))
+                - (px(x - 1, y - 1) + 2.0 * px(x - 1, y) + px(x - 1, y + 1));
+            let gy = (px(x - 1, y + 1) + 2.0 * px(x, y + 1) + px(x + 1, y + 1))
+                - (px(x - 1, y - 1) + 2.0 * px(x, y - 1) + px(x + 1, y - 1));
+            let (gx, gy) = (gx / (4.0 * 255.0), gy / (4.0 * 255.0) / cell_aspect);
+            gradients.push((gx, gy, (gx * gx + gy * gy).sqrt()));
+        }
+    }
+
+    let magnitude = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= w || y >= h { 0.0 } else { gradients[(y * w + x) as usize].2 }
+    };
+
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (gx, gy, mag) = gradients[(y * w + x) as usize];
+            if mag < threshold {
+                return None;
+            }
+
+            // Gradient direction folded into [0, 180) degrees
+            let angle = gy.atan2(gx).to_degrees().rem_euclid(180.0);
+            let (glyph, (dx, dy)) = match angle {
+                a if !(22.5..157.5).contains(&a) => ('|', (1, 0)),
+                a if a < 67.5 => ('/', (1, 1)),
+                a if a < 112.5 => ('-', (0, 1)),
+                _ => ('\\', (-1, 1)),
+            };
+
+            // Non-maximum suppression: keep only the ridge across the edge
+            let is_max = mag >= magnitude(x + dx, y + dy) && mag >= magnitude(x - dx, y - dy);
+            is_max.then_some(glyph)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn test_select_edge_mode() {
+        assert_eq!(select_edge_mode("overlay").unwrap(), EdgeMode::Overlay);
+        assert!(select_edge_mode("sideways").is_err());
+    }
+
+    #[test]
+    fn test_detect_edges_directions() {
+        // Vertical step
+        let vertical = GrayImage::from_fn(6, 6, |x, _| Luma([if x < 3 { 0 } else { 255 }]));
+        let edges = detect_edges(&vertical, 0.25, 1.0);
+        assert!(edges.iter().flatten().all(|&c| c == '|'));
+        assert!(edges.iter().flatten().count() >= 6);
+
+        // Horizontal step
+        let horizontal = GrayImage::from_fn(6, 6, |_, y| Luma([if y < 3 { 0 } else { 255 }]));
+        assert!(detect_edges(&horizontal, 0.25, 1.0).iter().flatten().all(|&c| c == '-'));
+
+        // Rising diagonal (bright below-right of the line x + y = 5)
+        let diagonal = GrayImage::from_fn(6, 6, |x, y| Luma([if x + y < 5 { 0 } else { 255 }]));
+        assert!(detect_edges(&diagonal, 0.25, 1.0).iter().flatten().all(|&c| c == '/'));
+
+        // Flat images have no edges
+        let flat = GrayImage::from_pixel(6, 6, Luma([128]));
+        assert!(detect_edges(&flat, 0.25, 1.0).iter().all(Option::is_none));
+    }
+}
@@ -0,0 +1,178 @@
+//! Character grid produced by conversion, and its text, ANSI and HTML renderers
+
+use anyhow::Result;
+use crate::ansi::{push_bg, push_fg, push_skip, AnsiColor, ColorMode, RESET};
+use crate::html_generation::{grid_to_html, HtmlConfig};
+use crate::image_processing::RenderMode;
+use unicode_width::UnicodeWidthChar;
+
+/// One character cell of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub glyph: char,
+    /// Foreground color, when converting with color
+    pub fg: Option<AnsiColor>,
+    /// Background color, used by the block modes
+    pub bg: Option<AnsiColor>,
+    /// Average source alpha under the cell, when transparent cells are requested
+    pub alpha: Option<u8>,
+}
+
+impl Cell {
+    /// Whether the cell is mostly transparent and left undrawn
+    pub fn is_empty(&self) -> bool {
+        self.alpha.is_some_and(|a| a < 128)
+    }
+}
+
+/// How a grid was produced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridMetadata {
+    /// Dimensions of the source image in pixels
+    pub source_size: (u32, u32),
+    pub mode: RenderMode,
+    pub color: ColorMode,
+}
+
+/// Converted image as a grid of cells in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsciiGrid {
+    /// Cells per row
+    pub cols: u32,
+    /// Number of rows
+    pub rows: u32,
+    /// Terminal columns each cell occupies (2 for full-width ramps)
+    pub cell_width: u32,
+    pub cells: Vec<Cell>,
+    pub metadata: GridMetadata,
+}
+
+impl AsciiGrid {
+    /// Display width in terminal columns
+    pub fn width(&self) -> u32 {
+        self.cols * self.cell_width
+    }
+
+    /// The cell at column `x` of row `y`
+    pub fn cell(&self, x: u32, y: u32) -> &Cell {
+        &self.cells[(y * self.cols + x) as usize]
+    }
+
+    /// Iterate over the rows of cells
+    pub fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.cols.max(1) as usize)
+    }
+
+    /// Plain text: glyphs only, with empty cells as spaces
+    pub fn to_text(&self) -> String {
+        self.render(false)
+    }
+
+    /// Text with ANSI color escapes; empty cells are skipped with cursor movement
+    pub fn to_ansi(&self) -> String {
+        self.render(true)
+    }
+
+    /// A standalone HTML page with colors as `<span>` styles
+    pub fn to_html(&self, config: HtmlConfig) -> Result<String> {
+        grid_to_html(self, config)
+    }
+
+    fn render(&self, ansi: bool) -> String {
+        // Most glyphs are 1-4 bytes of UTF-8 (+1 for newline per row)
+        let mut out = String::with_capacity(self.cells.len() * 4 + self.rows as usize);
+        for row in self.rows() {
+            push_row(&mut out, self.cell_width as usize, row, ansi);
+        }
+        out
+    }
+}
+
+/// Append one row of cells, merging runs of identical colors into one escape.
+/// Glyphs narrower than `cell_width` are padded with spaces. Empty cells are
+/// skipped with cursor movement in ANSI output, so whatever is already on
+/// screen shows through, and with spaces otherwise; trailing empty cells are
+/// dropped.
+pub(crate) fn push_row(out: &mut String, cell_width: usize, cells: &[Cell], ansi: bool) {
+    let mut last_fg: Option<AnsiColor> = None;
+    let mut last_bg: Option<AnsiColor> = None;
+    let mut skipped = 0;
+    for cell in cells {
+        if cell.is_empty() {
+            skipped += cell_width;
+            continue;
+        }
+        if skipped > 0 {
+            match ansi {
+                true => push_skip(out, skipped),
+                false => out.extend(std::iter::repeat_n(' ', skipped)),
+            }
+            skipped = 0;
+        }
+        if ansi {
+            if let Some(fg) = cell.fg {
+                if last_fg != Some(fg) {
+                    push_fg(out, fg);
+                    last_fg = Some(fg);
+                }
+            }
+            if let Some(bg) = cell.bg {
+                if last_bg != Some(bg) {
+                    push_bg(out, bg);
+                    last_bg = Some(bg);
+                }
+            }
+        }
+        out.push(cell.glyph);
+        for _ in cell.glyph.width().unwrap_or(1)..cell_width {
+            out.push(' ');
+        }
+    }
+    if last_fg.is_some() || last_bg.is_some() {
+        out.push_str(RESET);
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html_generation::ascii_to_html;
+
+    fn grid(cells: Vec<Cell>, cols: u32) -> AsciiGrid {
+        let rows = cells.len() as u32 / cols;
+        let metadata = GridMetadata { source_size: (cols, rows), mode: RenderMode::Ramp, color: ColorMode::Truecolor };
+        AsciiGrid { cols, rows, cell_width: 1, cells, metadata }
+    }
+
+    fn cell(glyph: char, fg: Option<AnsiColor>, alpha: Option<u8>) -> Cell {
+        Cell { glyph, fg, bg: None, alpha }
+    }
+
+    #[test]
+    fn test_grid_renderers() {
+        let red = Some(AnsiColor::Rgb([255, 0, 0]));
+        let grid = grid(vec![
+            cell('#', red, None), cell('<', red, None), cell('@', None, Some(0)),
+            cell('.', None, Some(10)), cell('@', Some(AnsiColor::Indexed(12)), Some(200)), cell('@', None, Some(0)),
+        ], 3);
+
+        assert_eq!(grid.width(), 3);
+        assert_eq!(grid.cell(1, 1).glyph, '@');
+        assert_eq!(grid.to_text(), "#<\n @\n");
+        assert_eq!(grid.to_ansi(), "\x1b[38;2;255;0;0m#<\x1b[0m\n\x1b[1C\x1b[94m@\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_grid_html_matches_ansi() {
+        let blue = Some(AnsiColor::Indexed(21));
+        let grid = grid(vec![
+            cell('&', blue, None), cell('x', None, Some(0)), cell('#', blue, None),
+            cell('#', Some(AnsiColor::Rgb([1, 2, 3])), None), cell(' ', blue, None), cell('x', None, Some(0)),
+        ], 3);
+
+        let html = grid.to_html(HtmlConfig::default()).unwrap();
+        assert_eq!(html, ascii_to_html(&grid.to_ansi(), HtmlConfig::default()).unwrap());
+        assert!(html.contains(r#"<span style="color:#0000ff">&amp;</span> <span style="color:#0000ff">#</span>"#));
+    }
+}
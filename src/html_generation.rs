@@ -1,6 +1,10 @@
 //! HTML generation module for converting ASCII art to web visualization
 
 use anyhow::Result;
+use crate::ansi::AnsiColor;
+use crate::grid::AsciiGrid;
+use crate::palette::xterm_rgb;
+use unicode_width::UnicodeWidthChar;
 
 /// Configuration for HTML generation
 #[derive(Debug, Clone)]
@@ -22,17 +26,205 @@ impl Default for HtmlConfig {
     }
 }
 
+/// A hex color as CSS, passing `transparent` through
+fn css_color(color: &str) -> String {
+    match color {
+        "transparent" => color.to_string(),
+        hex => format!("#{hex}"),
+    }
+}
+
+/// Read an extended SGR color (`5;n` or `2;r;g;b`) starting at `params[0]`,
+/// returning the color and the number of parameters consumed
+fn sgr_extended_color(params: &[u32]) -> (Option<[u8; 3]>, usize) {
+    match params {
+        [2, r, g, b, ..] => (Some([*r, *g, *b].map(|c| c.min(255) as u8)), 4),
+        [5, n, ..] => (Some(xterm_rgb((*n).min(255) as u8)), 2),
+        _ => (None, params.len()),
+    }
+}
+
+/// Append escaped text, first reopening the color span if the colors changed
+fn push_text(
+    body: &mut String,
+    text: &str,
+    span_open: &mut bool,
+    dirty: &mut bool,
+    fg: Option<[u8; 3]>,
+    bg: Option<[u8; 3]>,
+) {
+    if text.is_empty() {
+        return;
+    }
+    if *dirty {
+        if *span_open {
+            body.push_str("</span>");
+            *span_open = false;
+        }
+        let mut style = String::new();
+        if let Some([r, g, b]) = fg {
+            style.push_str(&format!("color:#{r:02x}{g:02x}{b:02x}"));
+        }
+        if let Some([r, g, b]) = bg {
+            if !style.is_empty() {
+                style.push(';');
+            }
+            style.push_str(&format!("background-color:#{r:02x}{g:02x}{b:02x}"));
+        }
+        if !style.is_empty() {
+            body.push_str(&format!(r#"<span style="{style}">"#));
+            *span_open = true;
+        }
+        *dirty = false;
+    }
+    body.push_str(&html_escape::encode_text(text));
+}
+
+/// Convert ANSI-colored ASCII art into escaped HTML, turning SGR color
+/// sequences into `<span>` elements. Plain text passes through escaped.
+fn ansi_to_html_body(ascii_content: &str) -> String {
+    let mut body = String::with_capacity(ascii_content.len());
+    let mut span_open = false;
+    let mut dirty = false;
+    let (mut fg, mut bg): (Option<[u8; 3]>, Option<[u8; 3]>) = (None, None);
+    let mut rest = ascii_content;
+
+    while let Some(pos) = rest.find('\x1b') {
+        push_text(&mut body, &rest[..pos], &mut span_open, &mut dirty, fg, bg);
+        rest = &rest[pos + 1..];
+
+        // Only CSI sequences are understood: ESC '[' params final-byte
+        let Some(params_and_rest) = rest.strip_prefix('[') else { continue };
+        let Some(end) = params_and_rest.find(|c: char| ('@'..='~').contains(&c)) else {
+            rest = "";
+            break;
+        };
+        let final_byte = params_and_rest.as_bytes()[end];
+        let params: Vec<u32> = params_and_rest[..end]
+            .split(';')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        rest = &params_and_rest[end + 1..];
+
+        if final_byte == b'C' {
+            // Cursor forward: skipped cells stay transparent, outside any color span
+            if span_open {
+                body.push_str("</span>");
+                span_open = false;
+            }
+            let n = params.first().copied().unwrap_or(1).max(1);
+            body.extend(std::iter::repeat_n(' ', n as usize));
+            dirty = true;
+            continue;
+        }
+        if final_byte != b'm' {
+            continue;
+        }
+
+        let mut i = 0;
+        while i < params.len() {
+            let mut used = 1;
+            match params[i] {
+                0 => (fg, bg) = (None, None),
+                39 => fg = None,
+                49 => bg = None,
+                n @ 30..=37 => fg = Some(xterm_rgb((n - 30) as u8)),
+                n @ 90..=97 => fg = Some(xterm_rgb((n - 90 + 8) as u8)),
+                n @ 40..=47 => bg = Some(xterm_rgb((n - 40) as u8)),
+                n @ 100..=107 => bg = Some(xterm_rgb((n - 100 + 8) as u8)),
+                38 => {
+                    let (color, n) = sgr_extended_color(&params[i + 1..]);
+                    fg = color;
+                    used += n;
+                }
+                48 => {
+                    let (color, n) = sgr_extended_color(&params[i + 1..]);
+                    bg = color;
+                    used += n;
+                }
+                _ => {}
+            }
+            i += used;
+        }
+
+        dirty = true;
+    }
+    push_text(&mut body, rest, &mut span_open, &mut dirty, fg, bg);
+    if span_open {
+        body.push_str("</span>");
+    }
+    body
+}
+
+/// Render a grid's cells as escaped HTML with `<span>` colors, producing the
+/// same markup as [`ascii_to_html`] on the grid's ANSI output
+fn grid_to_html_body(grid: &AsciiGrid) -> String {
+    let mut body = String::with_capacity(grid.cells.len() * 2);
+    let mut span_open = false;
+    let mut dirty = false;
+    let (mut fg, mut bg): (Option<AnsiColor>, Option<AnsiColor>) = (None, None);
+    let mut glyph = [0u8; 4];
+
+    for row in grid.rows() {
+        let mut skipped = 0;
+        for cell in row {
+            if cell.is_empty() {
+                skipped += grid.cell_width as usize;
+                continue;
+            }
+            if skipped > 0 {
+                if span_open {
+                    body.push_str("</span>");
+                    span_open = false;
+                }
+                body.extend(std::iter::repeat_n(' ', skipped));
+                skipped = 0;
+                dirty = true;
+            }
+            if cell.fg.is_some() && cell.fg != fg {
+                fg = cell.fg;
+                dirty = true;
+            }
+            if cell.bg.is_some() && cell.bg != bg {
+                bg = cell.bg;
+                dirty = true;
+            }
+            let (fg_rgb, bg_rgb) = (fg.map(AnsiColor::rgb), bg.map(AnsiColor::rgb));
+            push_text(&mut body, cell.glyph.encode_utf8(&mut glyph), &mut span_open, &mut dirty, fg_rgb, bg_rgb);
+            let pad = (grid.cell_width as usize).saturating_sub(cell.glyph.width().unwrap_or(1));
+            push_text(&mut body, &" ".repeat(pad), &mut span_open, &mut dirty, fg_rgb, bg_rgb);
+        }
+        if fg.is_some() || bg.is_some() {
+            (fg, bg) = (None, None);
+            dirty = true;
+        }
+        push_text(&mut body, "\n", &mut span_open, &mut dirty, None, None);
+    }
+    if span_open {
+        body.push_str("</span>");
+    }
+    body
+}
+
 /// Convert ASCII art text to HTML with the specified configuration
 pub fn ascii_to_html(ascii_content: &str, config: HtmlConfig) -> Result<String> {
-    let lines: Vec<&str> = ascii_content.lines().collect();
-    if lines.is_empty() {
+    if ascii_content.lines().next().is_none() {
         return Ok(String::new());
     }
-    
-    let _max_width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
-    let _height = lines.len();
-    
-    let html = format!(r#"<!DOCTYPE html>
+    Ok(html_page(&ansi_to_html_body(ascii_content), &config))
+}
+
+/// Convert a character grid to HTML with the specified configuration
+pub fn grid_to_html(grid: &AsciiGrid, config: HtmlConfig) -> Result<String> {
+    if grid.cells.is_empty() {
+        return Ok(String::new());
+    }
+    Ok(html_page(&grid_to_html_body(grid), &config))
+}
+
+/// Wrap an HTML body of ASCII art in the viewer page
+fn html_page(body: &str, config: &HtmlConfig) -> String {
+    format!(r#"<!DOCTYPE html>
 <html lang="en">
 <head>
     <meta charset="UTF-8">
@@ -42,7 +234,7 @@ pub fn ascii_to_html(ascii_content: &str, config: HtmlConfig) -> Result<String>
         body {{
             margin: 0;
             padding: 20px;
-            background-color: #{};
+            background-color: {};
             color: #{};
             font-family: {}, monospace;
             font-size: {}px;
@@ -114,19 +306,17 @@ pub fn ascii_to_html(ascii_content: &str, config: HtmlConfig) -> Result<String>
     </script>
 </body>
 </html>"#,
-        config.background_color,
+        css_color(&config.background_color),
         config.text_color,
         config.font_family,
         config.font_size,
         config.font_size.saturating_sub(1).max(1),
         config.font_size.saturating_sub(2).max(1),
         config.font_size.saturating_sub(3).max(1),
-        html_escape::encode_text(ascii_content),
+        body,
         config.font_size,
         config.font_size
-    );
-    
-    Ok(html)
+    )
 }
 
 #[cfg(test)]
@@ -150,4 +340,39 @@ mod tests {
         let html = ascii_to_html("", config).unwrap();
         assert_eq!(html, "");
     }
+
+    #[test]
+    fn test_ansi_colors_become_spans() {
+        let ascii = "\x1b[38;2;255;0;0m<@\x1b[0m\n";
+        let html = ascii_to_html(ascii, HtmlConfig::default()).unwrap();
+
+        assert!(html.contains(r#"<span style="color:#ff0000">&lt;@</span>"#));
+        assert!(!html.contains('\x1b'));
+    }
+
+    #[test]
+    fn test_ansi_palette_colors_become_spans() {
+        let html = ascii_to_html("\x1b[91m#\x1b[38;5;21m@\x1b[0m", HtmlConfig::default()).unwrap();
+
+        assert!(html.contains(r#"<span style="color:#ff0000">#</span>"#));
+        assert!(html.contains(r#"<span style="color:#0000ff">@</span>"#));
+    }
+
+    #[test]
+    fn test_ansi_background_colors_become_spans() {
+        let ascii = "\x1b[38;2;255;0;0m\x1b[48;5;21m▀\x1b[0m";
+        let html = ascii_to_html(ascii, HtmlConfig::default()).unwrap();
+
+        assert!(html.contains(r#"<span style="color:#ff0000;background-color:#0000ff">▀</span>"#));
+    }
+
+    #[test]
+    fn test_skipped_cells_are_transparent() {
+        let ascii = "\x1b[48;5;21m#\x1b[2C#\x1b[0m";
+        let config = HtmlConfig { background_color: "transparent".to_string(), ..Default::default() };
+        let html = ascii_to_html(ascii, config).unwrap();
+
+        assert!(html.contains(r#"<span style="background-color:#0000ff">#</span>  <span style="background-color:#0000ff">#</span>"#));
+        assert!(html.contains("background-color: transparent;"));
+    }
 }
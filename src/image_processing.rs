@@ -1,137 +1,723 @@
 //! Image processing module for converting images to ASCII art
 
 use anyhow::Result;
-use image::{DynamicImage, imageops::FilterType, GenericImageView};
+use image::{imageops, DynamicImage, GenericImageView, GrayImage, Luma, Rgb, RgbImage};
+use crate::ansi::{AnsiColor, ColorMode};
+use crate::blocks::{best_fit, half_block_char, quadrant_char, sextant_char};
+use crate::braille::braille_char;
+use crate::calibrate::calibrate_ramp;
+use crate::clahe::{ClaheConfig, TileHistograms, TileMappings};
+use crate::dither::{Dither, Quantizer};
+use crate::edges::{detect_edges, EdgeMode};
+use crate::grid::{push_row, AsciiGrid, Cell, GridMetadata};
+use crate::luminance::{from_linear, to_linear, LumaModel};
+use crate::sampler::{Grid, Resizer, Sampler};
+use crate::sizing::{layout, Fit};
+use crate::structural::{best_glyph, PATCH_SIZE};
+use crate::tone::{histogram, ToneConfig};
+use std::borrow::Cow;
+use std::io::Write;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
 
-/// Select the appropriate image filter based on the resizer name
-pub fn select_filter(name: &str) -> Result<Option<FilterType>> {
-    Ok(match name {
-        "nearest" => Some(FilterType::Nearest),
-        "triangle" => Some(FilterType::Triangle),
-        "lanczos3" => Some(FilterType::Lanczos3),
-        "pixel" => None, // Special case for pixel-by-pixel sampling
-        "1to1" => None, // Special case for 1:1 pixel mapping
-        other => anyhow::bail!("unknown resizer: {other}. Available options: nearest, triangle, lanczos3, pixel, 1to1"),
-    })
+/// Select and validate the glyph ramp for ASCII conversion.
+/// Ramps are sequences of Unicode scalar values, so multi-byte glyphs such as
+/// block shades or CJK characters stay intact.
+pub fn select_ramp(spec: &str) -> Result<Vec<char>> {
+    Ok(select_ramp_with_levels(spec)?.0)
 }
 
-/// Select and validate the glyph ramp for ASCII conversion
-pub fn select_ramp(spec: &str) -> Result<Vec<u8>> {
+/// Select the glyph ramp together with the luminance level (0–255) of each glyph.
+/// `auto:<chars>` ramps are ordered and calibrated against the bundled bitmap
+/// font and get non-uniform levels; any other ramp is evenly spaced (`None`).
+pub fn select_ramp_with_levels(spec: &str) -> Result<(Vec<char>, Option<Vec<f32>>)> {
+    if let Some(chars) = spec.strip_prefix("auto:") {
+        let calibrated = calibrate_ramp(chars)?;
+        let levels = calibrated.levels();
+        return Ok((calibrated.glyphs, Some(levels)));
+    }
+
     let s = match spec {
         "basic" => " .:-=+*#%@",
         "classic" => " .'`^\",:;Il!i<>*+_-?][}{)(|\\/tfjrxnuvczXYUJCLQ0OZmwqpdbkhao*#MW&8%B@$",
+        "blocks" => " ░▒▓█",
         _ => spec, // allow custom string directly
     };
-    let bytes = s.as_bytes();
-    if bytes.is_empty() {
+    let glyphs: Vec<char> = s.chars().collect();
+    if glyphs.is_empty() {
         anyhow::bail!("ramp cannot be empty");
     }
-    Ok(bytes.to_vec())
+    if let Some(bad) = glyphs.iter().find(|c| c.width().unwrap_or(0) == 0) {
+        anyhow::bail!("ramp glyph {bad:?} has no display width");
+    }
+    Ok((glyphs, None))
 }
 
-/// Convert an image to ASCII art using the specified parameters
-pub fn image_to_ascii(
-    img: &DynamicImage,
-    cols: u32,
-    cell_aspect: f32,
-    filter: Option<FilterType>,
-    resizer_name: &str,
-    ramp: Vec<u8>,
-) -> Result<String> {
+/// Display width in terminal columns of the widest glyph in the ramp
+/// (2 for CJK full-width ramps, otherwise 1)
+pub fn ramp_cell_width(ramp: &[char]) -> u32 {
+    ramp.iter().map(|c| c.width().unwrap_or(1)).max().unwrap_or(1).max(1) as u32
+}
+
+/// How each terminal cell is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// One glyph from the ramp per sample, chosen by luminance
+    #[default]
+    Ramp,
+    /// One U+2800 braille pattern per cell, 2×4 dots per cell
+    Braille,
+    /// `▀`/`▄` half blocks, 1×2 sub-pixels per cell
+    HalfBlock,
+    /// Quadrant block elements, 2×2 sub-pixels per cell
+    Quadrant,
+    /// Unicode 13 sextant block elements, 2×3 sub-pixels per cell
+    Sextant,
+    /// ASCII glyph whose bitmap best matches the cell's 8×8 source patch
+    Structural,
+}
+
+impl RenderMode {
+    /// Number of source samples per cell horizontally and vertically
+    pub fn subcells(self) -> (u32, u32) {
+        match self {
+            RenderMode::Ramp => (1, 1),
+            RenderMode::Braille => (2, 4),
+            RenderMode::HalfBlock => (1, 2),
+            RenderMode::Quadrant => (2, 2),
+            RenderMode::Sextant => (2, 3),
+            RenderMode::Structural => (PATCH_SIZE, PATCH_SIZE),
+        }
+    }
+}
+
+/// Select the render mode based on its CLI name
+pub fn select_render_mode(name: &str) -> Result<RenderMode> {
+    Ok(match name {
+        "ramp" => RenderMode::Ramp,
+        "braille" => RenderMode::Braille,
+        "half" | "halfblock" => RenderMode::HalfBlock,
+        "quadrant" => RenderMode::Quadrant,
+        "sextant" => RenderMode::Sextant,
+        "structural" => RenderMode::Structural,
+        other => anyhow::bail!(
+            "unknown render mode: {other}. Available options: ramp, braille, half, quadrant, sextant, structural"
+        ),
+    })
+}
+
+/// Configuration for ASCII conversion
+#[derive(Debug, Clone)]
+pub struct AsciiConfig {
+    pub cols: u32,
+    /// Rows to fit into; used by every fit but [`Fit::Width`]
+    pub rows: Option<u32>,
+    /// How the image fits `cols` and `rows`
+    pub fit: Fit,
+    pub cell_aspect: f32,
+    /// How the image is resampled onto the character grid
+    pub resizer: Resizer,
+    /// Grayscale conversion used for glyph selection
+    pub luma: LumaModel,
+    /// Resample in linear light instead of on gamma-encoded sRGB values
+    pub linear: bool,
+    pub ramp: Vec<char>,
+    /// Luminance level of each ramp glyph; `None` spaces them evenly
+    pub ramp_levels: Option<Vec<f32>>,
+    pub color: ColorMode,
+    pub dither: Dither,
+    pub mode: RenderMode,
+    /// Draw line glyphs along detected edges (ramp mode only)
+    pub edges: EdgeMode,
+    /// Minimum normalized Sobel magnitude (0.0–1.0) that counts as an edge
+    pub edge_threshold: f32,
+    /// Tone mapping applied to sampled luminance before glyph selection
+    pub tone: ToneConfig,
+    /// Local contrast equalization, applied before the tone controls
+    pub clahe: Option<ClaheConfig>,
+    /// Color that translucent pixels are composited over
+    pub matte: [u8; 3],
+    /// Leave mostly transparent cells empty (skipped with cursor movement)
+    /// instead of drawing the matte
+    pub transparent: bool,
+}
+
+impl Default for AsciiConfig {
+    fn default() -> Self {
+        Self {
+            cols: 120,
+            rows: None,
+            fit: Fit::Width,
+            cell_aspect: 2.0,
+            resizer: Resizer::Triangle,
+            luma: LumaModel::Rec709,
+            linear: false,
+            ramp: " .:-=+*#%@".chars().collect(),
+            ramp_levels: None,
+            color: ColorMode::None,
+            dither: Dither::None,
+            mode: RenderMode::Ramp,
+            edges: EdgeMode::Off,
+            edge_threshold: 0.25,
+            tone: ToneConfig::default(),
+            clahe: None,
+            matte: [0, 0, 0],
+            transparent: false,
+        }
+    }
+}
+
+/// Samples per cell and cell width in columns used by the configured render mode
+fn grid_layout(config: &AsciiConfig) -> ((u32, u32), u32) {
+    match config.mode {
+        RenderMode::Ramp => ((1, 1), ramp_cell_width(&config.ramp)),
+        mode => (mode.subcells(), 1),
+    }
+}
+
+/// Size of the sample grid an image of `dims` pixels is resampled onto
+pub(crate) fn sample_grid_size(config: &AsciiConfig, dims: (u32, u32)) -> (u32, u32) {
+    let (subcells, cell_width) = grid_layout(config);
+    let (grid, _) = grid_for(config, dims, subcells, cell_width);
+    (grid.width, grid.height)
+}
+
+/// Size of the output in terminal columns and rows for an image of `dims` pixels
+pub fn output_size(config: &AsciiConfig, dims: (u32, u32)) -> (u32, u32) {
+    let (subcells, cell_width) = grid_layout(config);
+    let (grid, _) = grid_for(config, dims, subcells, cell_width);
+    (grid.width / subcells.0 * cell_width, grid.height / subcells.1)
+}
+
+/// CLAHE tile size in samples: `tile_size` cells wide and tall enough to be
+/// square on screen
+fn clahe_tile(config: &AsciiConfig, clahe: &ClaheConfig, (sub_x, sub_y): (u32, u32), cell_width: u32) -> (u32, u32) {
+    let cell_aspect = grid_cell_aspect(config, cell_width);
+    let tile_rows = (clahe.tile_size as f32 / cell_aspect).round().max(1.0) as u32;
+    (clahe.tile_size * sub_x, tile_rows * sub_y)
+}
+
+/// Compute the CLAHE tile histograms `image_to_ascii` would use for this image,
+/// so a caller can smooth them across video frames and pass them back in
+/// through `ClaheConfig::histograms`. Returns `None` when CLAHE is off.
+pub fn clahe_histograms(img: &DynamicImage, config: &AsciiConfig) -> Option<TileHistograms> {
+    let clahe = config.clahe.as_ref()?;
+    let (subcells, cell_width) = grid_layout(config);
+    let samples = GridRows::new(flatten_alpha(img, config.matte), config, (config.luma, config.linear), subcells, cell_width);
+    let (gray, _) = samples.rows(0..samples.grid.height, false);
+    let (tile_w, tile_h) = clahe_tile(config, clahe, subcells, cell_width);
+    Some(TileHistograms::compute(&gray, tile_w, tile_h))
+}
+
+/// Composite translucent pixels over `matte`; opaque images are borrowed as-is
+fn flatten_alpha(img: &DynamicImage, matte: [u8; 3]) -> Cow<'_, DynamicImage> {
+    if !img.color().has_alpha() {
+        return Cow::Borrowed(img);
+    }
+    let rgba = img.to_rgba8();
+    Cow::Owned(DynamicImage::ImageRgb8(RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8, m: u8| ((c as u32 * a as u32 + m as u32 * (255 - a as u32) + 127) / 255) as u8;
+        Rgb([blend(r, matte[0]), blend(g, matte[1]), blend(b, matte[2])])
+    })))
+}
+
+/// The alpha channel of an image as a grayscale plane, or `None` when
+/// transparent cells are off or the image has no alpha channel
+fn alpha_plane(img: &DynamicImage, config: &AsciiConfig) -> Option<GrayImage> {
+    if !config.transparent || !img.color().has_alpha() {
+        return None;
+    }
     let (w, h) = img.dimensions();
-    let ramp_len = ramp.len();
-    
-    // Preallocate output string for performance
-    let mut out = String::new();
-    
-    match resizer_name {
-        "1to1" => {
-            // 1:1 pixel mapping - each original pixel becomes one ASCII character
-            // Ignore cols parameter, use original image dimensions
-            out.reserve(((w + 1) * h) as usize);
-            let gray = img.to_luma8();
-            
-            for y in 0..h {
-                for x in 0..w {
-                    let pixel = gray.get_pixel(x, y)[0];
-                    let idx = ((pixel as usize * (ramp_len - 1)) / 255).min(ramp_len - 1);
-                    out.push(ramp[idx] as char);
-                }
-                out.push('\n');
-            }
+    Some(match img {
+        DynamicImage::ImageLumaA8(buf) => GrayImage::from_fn(w, h, |x, y| Luma([buf.get_pixel(x, y)[1]])),
+        DynamicImage::ImageRgba8(buf) => GrayImage::from_fn(w, h, |x, y| Luma([buf.get_pixel(x, y)[3]])),
+        other => {
+            let rgba = other.to_rgba8();
+            GrayImage::from_fn(w, h, |x, y| Luma([rgba.get_pixel(x, y)[3]]))
         }
+    })
+}
+
+/// Height/width ratio of one cell on the sampled grid. Cells are taller than
+/// wide except in 1:1 mode, where every source pixel is one cell.
+fn grid_cell_aspect(config: &AsciiConfig, cell_width: u32) -> f32 {
+    match config.resizer {
+        Resizer::OneToOne => 1.0,
+        _ => config.cell_aspect / cell_width as f32,
+    }
+}
+
+/// Lay out the sample grid for an image of `w`×`h` pixels, along with the
+/// source region to crop to first when the fit calls for it
+fn grid_for(
+    config: &AsciiConfig,
+    (w, h): (u32, u32),
+    (sub_x, sub_y): (u32, u32),
+    cell_width: u32,
+) -> (Grid, Option<(u32, u32, u32, u32)>) {
+    let grid = match config.resizer {
+        // 1:1 pixel mapping - each original pixel becomes one ASCII character (or dot)
+        // Ignore cols parameter, use original image dimensions, padded to whole cells
+        Resizer::OneToOne => Grid {
+            width: w.div_ceil(sub_x) * sub_x,
+            height: h.div_ceil(sub_y) * sub_y,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            cell_aspect: 1.0,
+        },
         _ => {
-            // Calculate scale factor and output rows for other modes
-            let scale = cols as f32 / w as f32;
-            let rows = ((h as f32 * scale) / cell_aspect).max(1.0).round() as u32;
-            
-            // Preallocate output string for performance (+1 for newline per row)
-            out.reserve(((cols + 1) * rows) as usize);
-            
-            match filter {
-                Some(filter_type) => {
-                    // Traditional approach: resize first, then convert
-                    let gray = img.resize_exact(cols, rows, filter_type).to_luma8();
-                    
-                    for y in 0..rows {
-                        for x in 0..cols {
-                            let pixel = gray.get_pixel(x, y)[0];
-                            let idx = ((pixel as usize * (ramp_len - 1)) / 255).min(ramp_len - 1);
-                            out.push(ramp[idx] as char);
-                        }
-                        out.push('\n');
+            // Fit the image to the requested columns and rows
+            let cols = (config.cols / cell_width).max(1);
+            let layout = layout((w, h), cols, config.rows, config.fit, grid_cell_aspect(config, cell_width));
+            let grid = Grid {
+                width: layout.cols * sub_x,
+                height: layout.rows * sub_y,
+                scale_x: layout.scale * sub_x as f32,
+                scale_y: layout.scale * sub_y as f32,
+                cell_aspect: layout.cell_aspect,
+            };
+            return (grid, layout.crop);
+        }
+    };
+    (grid, None)
+}
+
+/// Source rows sampled onto the grid on demand, a band at a time where the
+/// resizer can sample part of the grid and from one whole-grid sample where
+/// it can't
+struct GridRows<'a> {
+    img: Cow<'a, DynamicImage>,
+    resizer: &'a Resizer,
+    luma: LumaModel,
+    grid: Grid,
+    /// Source region the grid covers: `(x, y, width, height)`
+    region: (u32, u32, u32, u32),
+    /// Luminance and colors of the whole grid
+    whole: Option<(GrayImage, RgbImage)>,
+}
+
+impl<'a> GridRows<'a> {
+    /// Lay out the grid for `img`; resampling in linear light and resizers
+    /// that can't sample part of the grid sample all of it here
+    fn new(
+        img: Cow<'a, DynamicImage>,
+        config: &'a AsciiConfig,
+        (luma, linear): (LumaModel, bool),
+        subcells: (u32, u32),
+        cell_width: u32,
+    ) -> Self {
+        let (grid, crop) = grid_for(config, img.dimensions(), subcells, cell_width);
+        let region = crop.unwrap_or((0, 0, img.width(), img.height()));
+        let mut rows = Self { img, resizer: &config.resizer, luma, grid, region, whole: None };
+        if linear || !config.resizer.samples_rows() {
+            let (x, y, w, h) = region;
+            let cropped = match crop {
+                Some(_) => Cow::Owned(rows.img.crop_imm(x, y, w, h)),
+                None => Cow::Borrowed(rows.img.as_ref()),
+            };
+            let sampled = match linear {
+                // Resample in linear light, then convert the re-encoded sRGB
+                true => DynamicImage::ImageRgb8(from_linear(&config.resizer.sample(&to_linear(&cropped), &grid))),
+                false => config.resizer.sample(&cropped, &grid),
+            };
+            rows.whole = Some((luma.to_gray(&sampled), sampled.to_rgb8()));
+        }
+        rows
+    }
+
+    /// Luminance of grid rows `rows`, and their colors if `color` is set
+    fn rows(&self, rows: Range<u32>, color: bool) -> (GrayImage, Option<RgbImage>) {
+        let height = rows.len() as u32;
+        match &self.whole {
+            Some((gray, rgb)) => {
+                let gray = imageops::crop_imm(gray, 0, rows.start, self.grid.width, height).to_image();
+                (gray, color.then(|| imageops::crop_imm(rgb, 0, rows.start, self.grid.width, height).to_image()))
+            }
+            None => {
+                let sampled = self.resizer.sample_rows(&self.img, self.region, &self.grid, rows);
+                (self.luma.to_gray(&sampled), color.then(|| sampled.to_rgb8()))
+            }
+        }
+    }
+}
+
+/// Sample rows past each end of a band that are sampled along with it: error
+/// diffusion carries error two rows down, and edge detection compares each
+/// sample's gradient with those of its neighbours
+const BAND_MARGIN: u32 = 2;
+
+/// Samples that a band holds, at least one row's worth
+const BAND_SAMPLES: u32 = 1 << 18;
+
+/// The sample grid of an image with CLAHE and tone mapping applied, read a
+/// band of cell rows at a time. Mappings that depend on the whole grid's
+/// histograms are worked out up front.
+struct Samples<'a> {
+    image: GridRows<'a>,
+    /// Alpha plane on the same grid, with transparent cells on
+    alpha: Option<GridRows<'a>>,
+    color: bool,
+    subcells: (u32, u32),
+    clahe: Option<TileMappings>,
+    /// Tone lookup table, unless tone mapping is off
+    tone: Option<[u8; 256]>,
+}
+
+impl<'a> Samples<'a> {
+    /// Sample `img` with `subcells` samples per cell and cells `cell_width`
+    /// columns wide, like [`output_size`]
+    fn new(img: &'a DynamicImage, config: &'a AsciiConfig, subcells: (u32, u32), cell_width: u32) -> Self {
+        let image = GridRows::new(flatten_alpha(img, config.matte), config, (config.luma, config.linear), subcells, cell_width);
+        let alpha = alpha_plane(img, config).map(|plane| {
+            GridRows::new(Cow::Owned(DynamicImage::ImageLuma8(plane)), config, (LumaModel::Rec709, false), subcells, cell_width)
+        });
+        let mut samples = Self { image, alpha, color: config.color != ColorMode::None, subcells, clahe: None, tone: None };
+
+        let size = (samples.image.grid.width, samples.image.grid.height);
+        let band = (BAND_SAMPLES / size.0.max(1)).max(1);
+        let bands = move || (0..size.1).step_by(band as usize).map(move |y| y..(y + band).min(size.1));
+        samples.clahe = config.clahe.as_ref().map(|clahe| match &clahe.histograms {
+            Some(histograms) if histograms.fits_size(size) => histograms.mappings(clahe.clip_limit),
+            _ => {
+                let (tile_w, tile_h) = clahe_tile(config, clahe, subcells, cell_width);
+                let mut histograms = TileHistograms::empty(size, tile_w, tile_h);
+                for rows in bands() {
+                    let start = rows.start;
+                    histograms.add_rows(&samples.image.rows(rows, false).0, start);
+                }
+                histograms.normalize();
+                histograms.mappings(clahe.clip_limit)
+            }
+        });
+        if !config.tone.is_identity() {
+            let mut hist = [0; 256];
+            if config.tone.needs_histogram() {
+                for rows in bands() {
+                    let (gray, _) = samples.rows(rows, false);
+                    for (count, h) in hist.iter_mut().zip(histogram(gray.pixels().map(|p| p[0]))) {
+                        *count += h;
                     }
                 }
+            }
+            samples.tone = Some(config.tone.lut(&hist));
+        }
+        samples
+    }
+
+    /// Grid size in cells
+    fn cells(&self) -> (u32, u32) {
+        (self.image.grid.width / self.subcells.0, self.image.grid.height / self.subcells.1)
+    }
+
+    /// Tone-mapped luminance of sample rows `rows`, and their colors if `color` is set
+    fn rows(&self, rows: Range<u32>, color: bool) -> (GrayImage, Option<RgbImage>) {
+        let first = rows.start;
+        let (mut gray, rgb) = self.image.rows(rows, color);
+        if let Some(clahe) = &self.clahe {
+            clahe.apply(&mut gray, first);
+        }
+        if let Some(lut) = &self.tone {
+            gray.pixels_mut().for_each(|p| p[0] = lut[p[0] as usize]);
+        }
+        (gray, rgb)
+    }
+
+    /// The samples of cell rows `cells`, with [`BAND_MARGIN`] rows either
+    /// side where the grid has them
+    fn band(&self, cells: Range<u32>) -> Band {
+        let sub_y = self.subcells.1;
+        let top = (cells.start * sub_y).saturating_sub(BAND_MARGIN);
+        let bottom = (cells.end * sub_y + BAND_MARGIN).min(self.image.grid.height);
+        let (gray, rgb) = self.rows(top..bottom, self.color);
+        Band { cells, gray, rgb, top }
+    }
+
+    /// Average alpha of each cell in cell rows `cells`. Empty when transparent
+    /// cells are off or the image has no alpha channel.
+    fn alpha(&self, cells: &Range<u32>) -> Vec<u8> {
+        let Some(alpha) = &self.alpha else { return Vec::new() };
+        let (sub_x, sub_y) = self.subcells;
+        let (alpha, _) = alpha.rows(cells.start * sub_y..cells.end * sub_y, false);
+        let (cols, rows) = (alpha.width() / sub_x, alpha.height() / sub_y);
+        (0..rows)
+            .flat_map(|y| (0..cols).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let sum: u32 = (0..sub_y)
+                    .flat_map(|dy| (0..sub_x).map(move |dx| (x * sub_x + dx, y * sub_y + dy)))
+                    .map(|(px, py)| alpha.get_pixel(px, py)[0] as u32)
+                    .sum();
+                (sum / (sub_x * sub_y)) as u8
+            })
+            .collect()
+    }
+}
+
+/// Glyph with its foreground and background colors
+type Styled = (char, Option<AnsiColor>, Option<AnsiColor>);
+
+/// Appends the cells of a band of rows to a buffer
+type BandFn<'a> = Box<dyn FnMut(Range<u32>, &mut Vec<Cell>) + 'a>;
+
+/// A rendered image whose cells are computed on demand, a band of rows at a time
+struct Cells<'a> {
+    cols: u32,
+    rows: u32,
+    cell_width: u32,
+    /// Rows of cells in a band of [`BAND_SAMPLES`]
+    band_rows: u32,
+    /// Called with bands in order
+    band: BandFn<'a>,
+}
+
+/// The samples of a band of cell rows
+struct Band {
+    cells: Range<u32>,
+    gray: GrayImage,
+    rgb: Option<RgbImage>,
+    /// Sample row the images start at, [`BAND_MARGIN`] rows above the band
+    /// unless the grid starts sooner
+    top: u32,
+}
+
+impl<'a> Cells<'a> {
+    /// Cells of `samples`, where `style` takes the samples of each band and
+    /// gives the glyph and colors of each of its cells
+    fn new<F>(samples: Samples<'a>, cell_width: u32, mut style: impl FnMut(Band) -> F + 'a) -> Self
+    where
+        F: Fn(u32, u32) -> Styled,
+    {
+        let (cols, rows) = samples.cells();
+        let band_rows = (BAND_SAMPLES / (samples.image.grid.width * samples.subcells.1).max(1)).max(1);
+        let band = move |cells: Range<u32>, out: &mut Vec<Cell>| {
+            let alpha = samples.alpha(&cells);
+            let cell = style(samples.band(cells.clone()));
+            for y in cells.clone() {
+                out.extend((0..cols).map(|x| {
+                    let (glyph, fg, bg) = cell(x, y);
+                    Cell { glyph, fg, bg, alpha: alpha.get(((y - cells.start) * cols + x) as usize).copied() }
+                }));
+            }
+        };
+        Self { cols, rows, cell_width, band_rows, band: Box::new(band) }
+    }
+
+    /// Replace the contents of `buf` with the cells of `rows`, row-major
+    fn rows(&mut self, rows: Range<u32>, buf: &mut Vec<Cell>) {
+        buf.clear();
+        (self.band)(rows, buf);
+    }
+}
+
+/// Convert an image to ASCII art using the specified configuration
+pub fn image_to_ascii(img: &DynamicImage, config: &AsciiConfig) -> Result<String> {
+    Ok(image_to_grid(img, config)?.to_ansi())
+}
+
+/// Convert an image to a grid of cells that can be rendered as text, ANSI or HTML
+pub fn image_to_grid(img: &DynamicImage, config: &AsciiConfig) -> Result<AsciiGrid> {
+    let mut source = render(img, config)?;
+    let mut cells = Vec::with_capacity((source.cols * source.rows) as usize);
+    source.rows(0..source.rows, &mut cells);
+    let metadata = GridMetadata { source_size: img.dimensions(), mode: config.mode, color: config.color };
+    Ok(AsciiGrid { cols: source.cols, rows: source.rows, cell_width: source.cell_width, cells, metadata })
+}
+
+/// Write the same output as [`image_to_ascii`] into `out` a band of rows at a
+/// time. Resizers that read only the source pixels under each band (`pixel`,
+/// `1to1` and the area resizers, without linear-light resampling) hold just
+/// one band of samples, so memory stays bounded however large the grid is.
+pub fn write_ascii(img: &DynamicImage, config: &AsciiConfig, out: &mut impl Write) -> Result<()> {
+    let mut source = render(img, config)?;
+    let band = source.band_rows;
+    let mut cells = Vec::new();
+    let mut line = String::new();
+    for start in (0..source.rows).step_by(band as usize) {
+        source.rows(start..(start + band).min(source.rows), &mut cells);
+        for row in cells.chunks(source.cols as usize) {
+            line.clear();
+            push_row(&mut line, source.cell_width as usize, row, true);
+            out.write_all(line.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn render<'a>(img: &'a DynamicImage, config: &'a AsciiConfig) -> Result<Cells<'a>> {
+    config.tone.validate()?;
+    if config.fit != Fit::Width && config.rows.unwrap_or(0) == 0 {
+        anyhow::bail!("fitting the image with {:?} needs a positive row count", config.fit);
+    }
+    if let Some(clahe) = &config.clahe {
+        clahe.validate()?;
+    }
+    match config.mode {
+        RenderMode::Ramp => render_ramp(img, config),
+        RenderMode::Braille => render_braille(img, config),
+        RenderMode::HalfBlock | RenderMode::Quadrant | RenderMode::Sextant => render_blocks(img, config),
+        RenderMode::Structural => render_structural(img, config),
+    }
+}
+
+/// Map each sample's luminance onto a glyph from the ramp
+fn render_ramp<'a>(img: &'a DynamicImage, config: &'a AsciiConfig) -> Result<Cells<'a>> {
+    let ramp = &config.ramp;
+    let ramp_len = ramp.len();
+    if ramp_len == 0 {
+        anyhow::bail!("ramp cannot be empty");
+    }
+
+    let cell_width = ramp_cell_width(ramp);
+    let samples = Samples::new(img, config, (1, 1), cell_width);
+    let (cols, rows) = samples.cells();
+    let mut quantizer = match &config.ramp_levels {
+        Some(levels) if levels.len() == ramp_len => Quantizer::new(levels, config.dither, (cols, rows)),
+        Some(_) => anyhow::bail!("ramp_levels must have one level per ramp glyph"),
+        None => Quantizer::even(ramp_len, config.dither, (cols, rows)),
+    };
+    let cell_aspect = grid_cell_aspect(config, cell_width);
+
+    Ok(Cells::new(samples, cell_width, move |Band { cells, gray, rgb, top }| {
+        let indices = quantizer.rows(&gray, top, cells.clone());
+        let edges = match config.edges {
+            EdgeMode::Off => None,
+            _ => Some(detect_edges(&gray, config.edge_threshold, cell_aspect)),
+        };
+        let first = cells.start;
+        move |x, y| {
+            let glyph = match edges.as_ref().and_then(|edges| edges[((y - top) * cols + x) as usize]) {
+                Some(edge) => edge,
+                None if config.edges == EdgeMode::Only => ' ',
+                None => ramp[indices[((y - first) * cols + x) as usize]],
+            };
+            let color = rgb.as_ref().and_then(|rgb| config.color.quantize(rgb.get_pixel(x, y - top).0));
+            (glyph, color, None)
+        }
+    }))
+}
+
+/// Threshold (or dither) a 2×4 dot grid per cell into braille patterns
+fn render_braille<'a>(img: &'a DynamicImage, config: &'a AsciiConfig) -> Result<Cells<'a>> {
+    let (sub_x, sub_y) = RenderMode::Braille.subcells();
+    let samples = Samples::new(img, config, (sub_x, sub_y), 1);
+    let dots_w = samples.image.grid.width;
+    let mut quantizer = Quantizer::even(2, config.dither, (dots_w, samples.image.grid.height));
+
+    Ok(Cells::new(samples, 1, move |Band { cells, gray, rgb, top }| {
+        let first = cells.start * sub_y;
+        let on = quantizer.rows(&gray, top, first..cells.end * sub_y);
+        move |x, y| {
+            let dot = |dx: u32, dy: u32| (x * sub_x + dx, y * sub_y + dy);
+            let is_on = |(px, py): (u32, u32)| on[((py - first) * dots_w + px) as usize] == 1;
+            let glyph = braille_char(|dx, dy| is_on(dot(dx, dy)));
+
+            // Color the cell with the average of its lit dots (or all dots if none are lit)
+            let color = rgb.as_ref().and_then(|rgb| {
+                let dots: Vec<(u32, u32)> = (0..sub_y).flat_map(|dy| (0..sub_x).map(move |dx| dot(dx, dy))).collect();
+                let lit: Vec<(u32, u32)> = dots.iter().copied().filter(|&d| is_on(d)).collect();
+                let chosen = if lit.is_empty() { &dots } else { &lit };
+                config.color.quantize(average_rgb(rgb, top, chosen))
+            });
+            (glyph, color, None)
+        }
+    }))
+}
+
+/// Pick a block-element glyph per cell. With color, each cell gets the glyph and
+/// fg/bg pair that best fits its sub-pixels; without, sub-pixels are thresholded.
+fn render_blocks<'a>(img: &'a DynamicImage, config: &'a AsciiConfig) -> Result<Cells<'a>> {
+    let (sub_x, sub_y) = config.mode.subcells();
+    let glyph_for: fn(u8) -> char = match config.mode {
+        RenderMode::HalfBlock => half_block_char,
+        RenderMode::Quadrant => quadrant_char,
+        _ => sextant_char,
+    };
+    let samples = Samples::new(img, config, (sub_x, sub_y), 1);
+    let px_w = samples.image.grid.width;
+    let mut quantizer = Quantizer::even(2, config.dither, (px_w, samples.image.grid.height));
+
+    Ok(Cells::new(samples, 1, move |Band { cells, gray, rgb, top }| {
+        let first = cells.start * sub_y;
+        let on = match rgb {
+            Some(_) => Vec::new(),
+            None => quantizer.rows(&gray, top, first..cells.end * sub_y),
+        };
+        move |x, y| {
+            // Sub-pixel positions in row-major order, matching the pattern bits
+            let positions = (0..sub_y).flat_map(|dy| (0..sub_x).map(move |dx| (x * sub_x + dx, y * sub_y + dy)));
+            match &rgb {
+                Some(rgb) => {
+                    let pixels: Vec<[u8; 3]> = positions.map(|(px, py)| rgb.get_pixel(px, py - top).0).collect();
+                    let (pattern, fg, bg) = best_fit(&pixels);
+                    (glyph_for(pattern), config.color.quantize(fg), config.color.quantize(bg))
+                }
                 None => {
-                    // Pixel-by-pixel approach: sample original image directly
-                    let gray = img.to_luma8();
-                    
-                    for y in 0..rows {
-                        for x in 0..cols {
-                            // Calculate the position in the original image
-                            let orig_x = ((x as f32 + 0.5) / scale) as u32;
-                            let orig_y = ((y as f32 + 0.5) / scale * cell_aspect) as u32;
-                            
-                            // Clamp to image bounds
-                            let orig_x = orig_x.min(w - 1);
-                            let orig_y = orig_y.min(h - 1);
-                            
-                            let pixel = gray.get_pixel(orig_x, orig_y)[0];
-                            let idx = ((pixel as usize * (ramp_len - 1)) / 255).min(ramp_len - 1);
-                            out.push(ramp[idx] as char);
-                        }
-                        out.push('\n');
-                    }
+                    let pattern = positions.enumerate().fold(0u8, |acc, (i, (px, py))| {
+                        acc | ((on[((py - first) * px_w + px) as usize] as u8) << i)
+                    });
+                    (glyph_for(pattern), None, None)
                 }
             }
         }
+    }))
+}
+
+/// Compare each cell's 8×8 source patch against the bundled font's glyph
+/// bitmaps and keep the structurally closest glyph
+fn render_structural<'a>(img: &'a DynamicImage, config: &'a AsciiConfig) -> Result<Cells<'a>> {
+    let samples = Samples::new(img, config, (PATCH_SIZE, PATCH_SIZE), 1);
+
+    Ok(Cells::new(samples, 1, move |Band { gray, rgb, top, .. }| {
+        move |x, y| {
+            let positions: Vec<(u32, u32)> = (0..PATCH_SIZE)
+                .flat_map(|dy| (0..PATCH_SIZE).map(move |dx| (x * PATCH_SIZE + dx, y * PATCH_SIZE + dy)))
+                .collect();
+            let mut patch = [0.0f32; 64];
+            for (p, &(px, py)) in patch.iter_mut().zip(&positions) {
+                *p = gray.get_pixel(px, py - top)[0] as f32 / 255.0;
+            }
+            let color = rgb.as_ref().and_then(|rgb| config.color.quantize(average_rgb(rgb, top, &positions)));
+            (best_glyph(&patch), color, None)
+        }
+    }))
+}
+
+/// Average color of the given pixel positions, in an image of the sample rows
+/// from `top` on
+fn average_rgb(rgb: &RgbImage, top: u32, positions: &[(u32, u32)]) -> [u8; 3] {
+    let mut sum = [0u32; 3];
+    for &(x, y) in positions {
+        let p = rgb.get_pixel(x, y - top).0;
+        for c in 0..3 {
+            sum[c] += p[c] as u32;
+        }
     }
-    
-    Ok(out)
+    let n = positions.len().max(1) as u32;
+    sum.map(|s| ((s + n / 2) / n) as u8)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{ImageBuffer, Luma};
-
-    #[test]
-    fn test_select_filter() {
-        assert!(select_filter("triangle").unwrap().is_some());
-        assert!(select_filter("pixel").unwrap().is_none());
-        assert!(select_filter("1to1").unwrap().is_none());
-        assert!(select_filter("invalid").is_err());
-    }
+    use image::ImageBuffer;
 
     #[test]
     fn test_select_ramp() {
         let basic = select_ramp("basic").unwrap();
-        assert_eq!(basic, b" .:-=+*#%@");
+        assert_eq!(basic, " .:-=+*#%@".chars().collect::<Vec<_>>());
         
         let custom = select_ramp("abc").unwrap();
-        assert_eq!(custom, b"abc");
+        assert_eq!(custom, vec!['a', 'b', 'c']);
+        
+        let shades = select_ramp(" ░▒▓█").unwrap();
+        assert_eq!(shades.len(), 5);
         
         assert!(select_ramp("").is_err());
+        assert!(select_ramp("a\tb").is_err());
+        
+        let (auto, levels) = select_ramp_with_levels("auto:@ .").unwrap();
+        assert_eq!(auto, vec![' ', '.', '@']);
+        assert_eq!(levels.unwrap().len(), 3);
     }
 
     #[test]
@@ -142,11 +728,157 @@ mod tests {
         });
         let dynamic_img = DynamicImage::ImageLuma8(img);
         
-        let ramp = select_ramp("basic").unwrap();
-        let result = image_to_ascii(&dynamic_img, 10, 2.0, None, "1to1", ramp).unwrap();
+        let config = AsciiConfig {
+            resizer: Resizer::OneToOne,
+            ..AsciiConfig::default()
+        };
+        let result = image_to_ascii(&dynamic_img, &config).unwrap();
         
         // Should have 2 lines (2 rows)
         let lines: Vec<&str> = result.lines().collect();
         assert_eq!(lines.len(), 2);
     }
+
+    #[test]
+    fn test_image_to_ascii_truecolor() {
+        // Two red cells followed by a blue one should emit exactly two color escapes
+        let img = ImageBuffer::from_fn(3, 1, |x, _| {
+            if x < 2 { Rgb([255u8, 0, 0]) } else { Rgb([0, 0, 255]) }
+        });
+        let dynamic_img = DynamicImage::ImageRgb8(img);
+
+        let config = AsciiConfig {
+            resizer: Resizer::OneToOne,
+            color: ColorMode::Truecolor,
+            ..AsciiConfig::default()
+        };
+        let result = image_to_ascii(&dynamic_img, &config).unwrap();
+
+        assert_eq!(result.matches("\x1b[38;2;").count(), 2);
+        assert!(result.contains("\x1b[38;2;255;0;0m"));
+        assert!(result.ends_with("\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_image_to_ascii_dither() {
+        // Flat mid-gray maps to a single glyph without dithering, a mix with it
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_pixel(16, 16, Luma([128u8])));
+        let mut config = AsciiConfig {
+            resizer: Resizer::OneToOne,
+            ramp: vec![' ', '@'],
+            ..AsciiConfig::default()
+        };
+        let plain = image_to_ascii(&img, &config).unwrap();
+        assert!(!plain.contains('@'));
+
+        config.dither = Dither::Bayer(4);
+        let dithered = image_to_ascii(&img, &config).unwrap();
+        assert_eq!(dithered.matches('@').count(), 128);
+    }
+
+    #[test]
+    fn test_image_to_ascii_braille() {
+        // Left half white, right half black: every cell lights its left dot column
+        let img = ImageBuffer::from_fn(40, 40, |x, _| Luma([if x % 2 == 0 { 255u8 } else { 0 }]));
+        let config = AsciiConfig {
+            cols: 20,
+            resizer: Resizer::Pixel,
+            mode: RenderMode::Braille,
+            ..AsciiConfig::default()
+        };
+        let result = image_to_ascii(&DynamicImage::ImageLuma8(img), &config).unwrap();
+        let lines: Vec<&str> = result.lines().collect();
+
+        // Rows follow the same cols/cell_aspect math as ramp mode
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|l| l.chars().count() == 20 && l.chars().all(|c| c == '\u{2847}')));
+    }
+
+    #[test]
+    fn test_image_to_ascii_half_blocks() {
+        // Red over blue in one cell: an upper half block, red on blue
+        let img = ImageBuffer::from_fn(1, 2, |_, y| if y == 0 { Rgb([255u8, 0, 0]) } else { Rgb([0, 0, 255]) });
+        let config = AsciiConfig {
+            resizer: Resizer::OneToOne,
+            color: ColorMode::Truecolor,
+            mode: RenderMode::HalfBlock,
+            ..AsciiConfig::default()
+        };
+        let result = image_to_ascii(&DynamicImage::ImageRgb8(img), &config).unwrap();
+
+        assert_eq!(result, "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[0m\n");
+    }
+
+    #[test]
+    fn test_image_to_ascii_wide_ramp() {
+        // Full-width glyphs take two columns, so 20 columns hold 10 cells
+        let img = ImageBuffer::from_fn(40, 40, |x, _| Luma([if x < 20 { 0u8 } else { 255 }]));
+        let config = AsciiConfig {
+            cols: 20,
+            resizer: Resizer::Pixel,
+            ramp: select_ramp(" 中").unwrap(),
+            ..AsciiConfig::default()
+        };
+        let result = image_to_ascii(&DynamicImage::ImageLuma8(img), &config).unwrap();
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines.len(), 10);
+        assert_eq!(lines[0], "          中中中中中");
+    }
+
+    #[test]
+    fn test_image_to_ascii_alpha() {
+        // Opaque white on the right, transparent (hidden red) on the left
+        let img = ImageBuffer::from_fn(4, 1, |x, _| image::Rgba(if x < 2 { [255, 0, 0, 0] } else { [255, 255, 255, 255] }));
+        let img = DynamicImage::ImageRgba8(img);
+        let config = AsciiConfig { resizer: Resizer::OneToOne, ..AsciiConfig::default() };
+
+        // Composited over the default black matte
+        assert_eq!(image_to_ascii(&img, &config).unwrap(), "  @@\n");
+        let white = AsciiConfig { matte: [255, 255, 255], ..config.clone() };
+        assert_eq!(image_to_ascii(&img, &white).unwrap(), "@@@@\n");
+
+        // Transparent cells are skipped with cursor movement
+        let transparent = AsciiConfig { transparent: true, ..config };
+        assert_eq!(image_to_ascii(&img, &transparent).unwrap(), "\x1b[2C@@\n");
+    }
+
+    #[test]
+    fn test_image_to_grid() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, _| image::Rgba([255, 255, 255, x as u8 * 80])));
+        let config = AsciiConfig { resizer: Resizer::OneToOne, transparent: true, ..AsciiConfig::default() };
+        let grid = image_to_grid(&img, &config).unwrap();
+
+        assert_eq!((grid.cols, grid.rows, grid.width()), (4, 2, 4));
+        assert_eq!(grid.metadata.source_size, (4, 2));
+        assert_eq!(grid.cell(3, 1).alpha, Some(240));
+        assert!(grid.cell(1, 0).is_empty());
+        assert_eq!(grid.to_ansi(), image_to_ascii(&img, &config).unwrap());
+        assert_eq!(grid.to_text().lines().next(), Some("  +%"));
+    }
+
+    #[test]
+    fn test_write_ascii_matches_image_to_ascii() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(40, 30, |x, y| image::Rgba([x as u8 * 6, y as u8 * 8, 90, (x * y) as u8])));
+        for mode in [RenderMode::Ramp, RenderMode::Braille, RenderMode::HalfBlock, RenderMode::Structural] {
+            let config = AsciiConfig { cols: 10, mode, color: ColorMode::Ansi256, transparent: true, ..AsciiConfig::default() };
+            let mut out = Vec::new();
+            write_ascii(&img, &config, &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), image_to_ascii(&img, &config).unwrap());
+            let grid = image_to_grid(&img, &config).unwrap();
+            assert_eq!(output_size(&config, (40, 30)), (grid.width(), grid.rows));
+        }
+    }
+
+    #[test]
+    fn test_write_ascii_in_several_bands() {
+        // Wide enough that a band holds only part of the grid
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_fn(2048, 300, |x, y| image::Luma([(x ^ y) as u8])));
+        for mode in [RenderMode::Ramp, RenderMode::Braille] {
+            let config = AsciiConfig { resizer: Resizer::OneToOne, mode, dither: Dither::FloydSteinberg, ..AsciiConfig::default() };
+            let mut out = Vec::new();
+            write_ascii(&img, &config, &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), image_to_ascii(&img, &config).unwrap());
+        }
+    }
 }
@@ -6,8 +6,51 @@
 pub mod image_processing;
 pub mod html_generation;
 pub mod cli_utils;
+pub mod ansi;
+pub mod palette;
+pub mod dither;
+pub mod braille;
+pub mod blocks;
+pub mod font;
+pub mod calibrate;
+pub mod structural;
+pub mod edges;
+pub mod tone;
+pub mod clahe;
+pub mod luminance;
+pub mod sampler;
+pub mod area;
+pub mod grid;
+pub mod tiled;
+pub mod sizing;
+pub mod preprocess;
+pub mod animation;
+pub mod player;
+pub mod video;
+pub mod movie;
+pub mod delta;
 
 // Re-export main functionality for easy use
-pub use image_processing::{image_to_ascii, select_filter, select_ramp};
-pub use html_generation::{ascii_to_html, HtmlConfig};
+pub use image_processing::{
+    clahe_histograms, image_to_ascii, image_to_grid, output_size, select_ramp, select_ramp_with_levels, select_render_mode, write_ascii, AsciiConfig, RenderMode,
+};
+pub use calibrate::{calibrate_ramp, CalibratedRamp};
+pub use ansi::{detect_color_mode, select_color_mode, ColorMode};
+pub use dither::{select_dither, Dither};
+pub use edges::{select_edge_mode, EdgeMode};
+pub use tone::ToneConfig;
+pub use clahe::{ClaheConfig, TileHistograms};
+pub use luminance::{select_luma_model, LumaModel};
+pub use area::{AreaSampler, Pooling};
+pub use sampler::{select_resizer, Grid, PointSampler, Resizer, Sampler};
+pub use grid::{AsciiGrid, Cell, GridMetadata};
+pub use tiled::decode_within;
+pub use animation::{animation_to_ascii, decode_frames, write_frame_dir, AnimationFrame, AsciiFrame, Frames};
+pub use player::{play, PlaybackStats};
+pub use movie::{MovieHeader, MovieReader, MovieWriter};
+pub use delta::{changed_runs, Run};
+pub use video::{parse_frame_size, select_pixel_format, PixelFormat, VideoReader};
+pub use preprocess::{parse_crop, Crop, Length, Preprocess};
+pub use sizing::{resolve_size, select_fit, terminal_size, Fit, Layout};
+pub use html_generation::{ascii_to_html, grid_to_html, HtmlConfig};
 pub use cli_utils::*;
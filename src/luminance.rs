@@ -0,0 +1,115 @@
+//! Grayscale conversion models and linear-light resampling
+
+use anyhow::Result;
+use image::{DynamicImage, GrayImage, Luma, Rgb, Rgb32FImage, RgbImage};
+use crate::palette::{linear_to_srgb, srgb_to_lab, srgb_to_linear};
+
+/// How an RGB pixel is reduced to one luminance value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LumaModel {
+    /// Rec.709 / sRGB weights on gamma-encoded values (0.2126, 0.7152, 0.0722)
+    #[default]
+    Rec709,
+    /// Rec.601 (SDTV, JPEG) weights (0.299, 0.587, 0.114)
+    Rec601,
+    /// Perceptual lightness L* from CIELAB, scaled to 0–255
+    Lightness,
+    /// A single channel
+    Red,
+    Green,
+    Blue,
+    /// Brightest channel (HSV value)
+    Max,
+    /// Darkest channel
+    Min,
+}
+
+/// Select the luminance model based on its CLI name
+pub fn select_luma_model(name: &str) -> Result<LumaModel> {
+    Ok(match name {
+        "rec709" | "709" => LumaModel::Rec709,
+        "rec601" | "601" => LumaModel::Rec601,
+        "lightness" | "lstar" => LumaModel::Lightness,
+        "red" | "r" => LumaModel::Red,
+        "green" | "g" => LumaModel::Green,
+        "blue" | "b" => LumaModel::Blue,
+        "max" => LumaModel::Max,
+        "min" => LumaModel::Min,
+        other => anyhow::bail!(
+            "unknown luminance model: {other}. Available options: rec709, rec601, lightness, red, green, blue, max, min"
+        ),
+    })
+}
+
+impl LumaModel {
+    /// Luminance of one sRGB pixel
+    pub fn luma(self, [r, g, b]: [u8; 3]) -> u8 {
+        match self {
+            // Same integer weights as the `image` crate's `to_luma8`
+            LumaModel::Rec709 => ((2126 * r as u32 + 7152 * g as u32 + 722 * b as u32) / 10000) as u8,
+            LumaModel::Rec601 => ((299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000) as u8,
+            LumaModel::Lightness => (srgb_to_lab([r, g, b])[0] * 2.55).round().clamp(0.0, 255.0) as u8,
+            LumaModel::Red => r,
+            LumaModel::Green => g,
+            LumaModel::Blue => b,
+            LumaModel::Max => r.max(g).max(b),
+            LumaModel::Min => r.min(g).min(b),
+        }
+    }
+
+    /// Convert a whole image to grayscale with this model
+    pub fn to_gray(self, img: &DynamicImage) -> GrayImage {
+        match self {
+            LumaModel::Rec709 => img.to_luma8(),
+            model => {
+                let rgb = img.to_rgb8();
+                GrayImage::from_fn(rgb.width(), rgb.height(), |x, y| Luma([model.luma(rgb.get_pixel(x, y).0)]))
+            }
+        }
+    }
+}
+
+/// Decode an image to linear-light `Rgb32F` so resampling averages light
+/// rather than gamma-encoded values, which would darken fine bright-on-dark detail
+pub fn to_linear(img: &DynamicImage) -> DynamicImage {
+    let rgb = img.to_rgb8();
+    DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(rgb.width(), rgb.height(), |x, y| {
+        Rgb(rgb.get_pixel(x, y).0.map(srgb_to_linear))
+    }))
+}
+
+/// Re-encode a linear-light image to 8-bit sRGB
+pub fn from_linear(img: &DynamicImage) -> RgbImage {
+    let linear = img.to_rgb32f();
+    RgbImage::from_fn(linear.width(), linear.height(), |x, y| Rgb(linear.get_pixel(x, y).0.map(linear_to_srgb)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luma_models() {
+        let rgb = DynamicImage::ImageRgb8(RgbImage::from_fn(4, 4, |x, y| Rgb([x as u8 * 60, y as u8 * 70, 200])));
+        assert_eq!(LumaModel::Rec709.to_gray(&rgb), rgb.to_luma8());
+
+        assert_eq!(LumaModel::Rec601.luma([255, 0, 0]), 76);
+        assert_eq!(LumaModel::Lightness.luma([255, 255, 255]), 255);
+        assert_eq!(LumaModel::Lightness.luma([119, 119, 119]), 128); // L* ≈ 50
+        assert_eq!(LumaModel::Blue.luma([1, 2, 3]), 3);
+        assert_eq!(LumaModel::Max.luma([1, 9, 3]), 9);
+        assert!(select_luma_model("ycbcr").is_err());
+    }
+
+    #[test]
+    fn test_linear_resize_keeps_brightness() {
+        use image::imageops::FilterType;
+
+        // Alternating black and white columns average to 50% light, not 50% code value
+        let stripes = DynamicImage::ImageRgb8(RgbImage::from_fn(8, 2, |x, _| Rgb([if x % 2 == 0 { 0 } else { 255 }; 3])));
+        let linear = from_linear(&to_linear(&stripes).resize_exact(1, 1, FilterType::Triangle));
+        assert_eq!(linear.get_pixel(0, 0)[0], 188);
+        let gamma = stripes.resize_exact(1, 1, FilterType::Triangle).to_rgb8();
+        assert!(gamma.get_pixel(0, 0)[0] < 140);
+    }
+}
@@ -1,7 +1,9 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Parser;
-use image::ImageReader;
-use rust_ascii::{image_to_ascii, select_filter, select_ramp, validate_image_args};
+use rust_ascii::{write_ascii, terminal_size, parse_byte_size, decode_within, decode_frames, animation_to_ascii, write_frame_dir, play, ConvertArgs};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(name = "asciirun")]
@@ -11,55 +13,85 @@ struct Args {
     /// Path to the input image file
     input: String,
     
-    /// Number of columns in the output (default: 120)
-    #[arg(long, default_value_t = 120)]
-    cols: u32,
+    #[command(flatten)]
+    convert: ConvertArgs,
     
-    /// Terminal cell aspect ratio (height/width, default: 2.0)
-    #[arg(long, default_value_t = 2.0)]
-    cell_aspect: f32,
-    
-    /// Resampling filter to use (nearest, triangle, lanczos3, pixel, 1to1)
-    #[arg(long, default_value = "triangle")]
-    resizer: String,
-    
-    /// Glyph ramp for mapping luminance to characters
-    #[arg(long, default_value = "basic")]
-    ramp: String,
+    /// Memory budget for decoding (e.g. 512M, 2G); larger PNG, TIFF and PGM/PPM images are decoded in strips
+    #[arg(long)]
+    max_memory: Option<String>,
     
     /// Output file path (default: stdout)
     #[arg(long)]
     output: Option<String>,
+    
+    /// Write every frame of an animated GIF, APNG or WebP to this directory for ascii-play
+    #[arg(long)]
+    frames_dir: Option<PathBuf>,
+    
+    /// Frame rate of the --frames-dir frames; frames are repeated or dropped to keep their timing
+    #[arg(long, default_value_t = 30)]
+    fps: u32,
+    
+    /// Times to play an animation in the terminal
+    #[arg(long, default_value_t = 1)]
+    loops: u32,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Validate arguments
-    validate_image_args(args.cols, args.cell_aspect)?;
+    anyhow::ensure!(args.loops >= 1, "--loops must be at least 1");
+    // Fit the terminal when printing to one and no size is given
+    let terminal = if args.output.is_none() { terminal_size() } else { None };
+    let config = args.convert.to_config(terminal, 120)?;
+    let preprocess = args.convert.to_preprocess()?;
+    let max_memory = args.max_memory.as_deref().map(parse_byte_size).transpose()?;
 
-    // Load and decode image
-    let img = ImageReader::open(&args.input)
-        .map_err(|e| anyhow::anyhow!("failed to open {}: {e}", args.input))?
-        .decode()
-        .map_err(|e| anyhow::anyhow!("failed to decode {}: {e}", args.input))?;
-
-    // Select filter and ramp
-    let filter = select_filter(&args.resizer)?;
-    let ramp = select_ramp(&args.ramp)?;
-    
-    // Convert to ASCII
-    let ascii = image_to_ascii(&img, args.cols, args.cell_aspect, filter, &args.resizer, ramp)?;
+    // Load, decode and preprocess image, in strips if it would not fit in the memory budget
+    let playing = args.output.is_none() && io::stdout().is_terminal();
+    let img = match max_memory {
+        Some(_) if args.frames_dir.is_some() => anyhow::bail!("--frames-dir cannot be combined with --max-memory"),
+        Some(_) if playing && decode_frames(Path::new(&args.input), &preprocess)?.is_animated() => {
+            anyhow::bail!("--max-memory cannot play animations; leave it out, or pass --output to convert the first frame")
+        }
+        Some(budget) => decode_within(Path::new(&args.input), &config, &preprocess, budget)?,
+        None => {
+            let mut frames = decode_frames(Path::new(&args.input), &preprocess)?;
+            if let Some(dir) = &args.frames_dir {
+                let frames = animation_to_ascii(frames, &config)?;
+                let written = write_frame_dir(dir, &frames, args.fps.max(1))?;
+                println!("{} frames saved to: {} (play with: ascii-play {} {})", written, dir.display(), dir.display(), args.fps.max(1));
+                return Ok(());
+            }
+            // Animations play in the terminal; anything else prints their first frame
+            if playing && frames.is_animated() {
+                let frames = animation_to_ascii(frames, &config)?;
+                let repeated = (0..args.loops).flat_map(|_| frames.iter().cloned().map(Ok));
+                play(repeated, false)?;
+                return Ok(());
+            }
+            frames.next().context("no frames to convert")??.image
+        }
+    };
     
-    // Output result
+    // Convert to ASCII, streaming rows to the output as they are rendered
     match args.output {
         Some(path) => {
-            std::fs::write(&path, ascii)
+            let file = File::create(&path)
+                .map_err(|e| anyhow::anyhow!("failed to write to {}: {e}", path))?;
+            let mut out = BufWriter::new(file);
+            write_ascii(&img, &config, &mut out)
+                .and_then(|()| Ok(out.flush()?))
                 .map_err(|e| anyhow::anyhow!("failed to write to {}: {e}", path))?;
             println!("ASCII art saved to: {}", path);
         }
         None => {
-            print!("{ascii}");
+            let mut out = BufWriter::new(io::stdout().lock());
+            match write_ascii(&img, &config, &mut out).and_then(|()| Ok(out.flush()?)) {
+                // The reader (e.g. `head` or a closed pager) went away early
+                Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {}
+                result => result?,
+            }
         }
     }
     Ok(())
@@ -0,0 +1,544 @@
+//! Single-file ASCII movies (`.asciimov`): converted frames with their
+//! timing, indexed for random access.
+//!
+//! All integers are little-endian. The file starts with a header:
+//!
+//! | Size | Field |
+//! |------|-------|
+//! | 8    | magic `ASCIIMOV` |
+//! | 2    | format version, currently 1 |
+//! | 2    | flags; bit 0: frames are raw-deflate compressed |
+//! | 4    | width in terminal columns |
+//! | 4    | height in rows |
+//! | 4    | cell aspect ratio (`f32`) |
+//! | 4, 4 | frame rate numerator and denominator; 0/0 for variable timing |
+//! | 1    | color mode: 0 none, 1 16-color, 2 256-color, 3 truecolor |
+//! | 4, n | length and UTF-8 bytes of the glyph ramp |
+//! | 1    | render mode: 0 ramp, 1 braille, 2 half, 3 quadrant, 4 sextant, 5 structural |
+//! | 4, 4 | source width and height in pixels |
+//! | 4    | keyframe interval; 0 for text frames |
+//!
+//! Frames follow back to back, each compressed on its own when the flag is
+//! set. Text frames are what `image_to_ascii` produced, ANSI escapes
+//! included. Cell frames are either keyframes holding every cell or deltas
+//! holding the runs of cells that changed since the previous frame (see
+//! [`crate::delta`]); a keyframe starts every interval and wherever a delta
+//! would be larger. Every cell frame has the header's size. No frame decompresses
+//! to more than 64 bytes per column of each row, plus 16 per row and 64 in
+//! all. Then comes the index:
+//! an 8-byte frame count and 25 bytes per frame (8-byte offset, 4-byte stored
+//! length, 8-byte timestamp and 4-byte duration, both in microseconds, and
+//! the frame kind: 0 text, 1 keyframe, 2 delta). The last 16 bytes are the
+//! index offset and the magic `ASCIIIDX`.
+
+use anyhow::{bail, ensure, Context, Result};
+use crate::animation::AsciiFrame;
+use crate::ansi::ColorMode;
+use crate::delta::{apply_delta, decode_keyframe, encode_delta, encode_keyframe};
+use crate::grid::{AsciiGrid, GridMetadata};
+use crate::image_processing::RenderMode;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"ASCIIMOV";
+const INDEX_MAGIC: &[u8; 8] = b"ASCIIIDX";
+const VERSION: u16 = 1;
+const FLAG_DEFLATE: u16 = 1;
+
+/// Most bytes one terminal column takes in a decompressed frame of any kind:
+/// a glyph with truecolor foreground and background escapes, or a run header
+/// and a fully colored cell
+const MAX_COLUMN_BYTES: u64 = 64;
+
+const FRAME_TEXT: u8 = 0;
+const FRAME_KEY: u8 = 1;
+const FRAME_DELTA: u8 = 2;
+
+const RENDER_MODES: [RenderMode; 6] = [
+    RenderMode::Ramp,
+    RenderMode::Braille,
+    RenderMode::HalfBlock,
+    RenderMode::Quadrant,
+    RenderMode::Sextant,
+    RenderMode::Structural,
+];
+
+/// Movie-wide settings stored in the header
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovieHeader {
+    /// Width in terminal columns
+    pub cols: u32,
+    pub rows: u32,
+    pub cell_aspect: f32,
+    /// Nominal frame rate as a fraction; `None` when frames have their own
+    /// durations (e.g. converted GIFs)
+    pub fps: Option<(u32, u32)>,
+    pub color: ColorMode,
+    pub ramp: String,
+    /// Deflate each frame
+    pub compressed: bool,
+    pub mode: RenderMode,
+    /// Dimensions of the source frames in pixels
+    pub source_size: (u32, u32),
+    /// Store cell grids as a keyframe every this many frames and deltas in
+    /// between; `None` stores each frame's ANSI text
+    pub keyframe_interval: Option<u32>,
+}
+
+impl MovieHeader {
+    /// Largest decompressed frame a movie of this size can hold
+    fn max_frame_len(&self) -> u64 {
+        (self.cols as u64 * MAX_COLUMN_BYTES + 16) * self.rows as u64 + 64
+    }
+}
+
+/// Where a frame is stored and when it is shown
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry {
+    offset: u64,
+    length: u32,
+    timestamp_us: u64,
+    duration_us: u32,
+    kind: u8,
+}
+
+/// Writes a movie one frame at a time; [`finish`](Self::finish) adds the index
+pub struct MovieWriter<W: Write> {
+    out: W,
+    compressed: bool,
+    keyframe_interval: Option<u32>,
+    /// Width in terminal columns and height in rows that every grid must have
+    size: (u32, u32),
+    max_frame_len: u64,
+    /// Grid of the last cell frame, which the next delta is taken against
+    previous: Option<AsciiGrid>,
+    position: u64,
+    elapsed: Duration,
+    index: Vec<IndexEntry>,
+}
+
+impl MovieWriter<BufWriter<File>> {
+    /// Create a movie file at `path`
+    pub fn create(path: &Path, header: &MovieHeader) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        Self::new(BufWriter::new(file), header)
+    }
+}
+
+impl<W: Write> MovieWriter<W> {
+    /// Write the header to `out`
+    pub fn new(mut out: W, header: &MovieHeader) -> Result<Self> {
+        let (fps_num, fps_den) = header.fps.unwrap_or((0, 0));
+        let color = match header.color {
+            ColorMode::None => 0u8,
+            ColorMode::Ansi16 => 1,
+            ColorMode::Ansi256 => 2,
+            ColorMode::Truecolor => 3,
+        };
+        ensure!(header.keyframe_interval != Some(0), "keyframe interval must be at least 1");
+        let flags = if header.compressed { FLAG_DEFLATE } else { 0 };
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&flags.to_le_bytes())?;
+        for value in [header.cols, header.rows, header.cell_aspect.to_bits(), fps_num, fps_den] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        out.write_all(&[color])?;
+        out.write_all(&(header.ramp.len() as u32).to_le_bytes())?;
+        out.write_all(header.ramp.as_bytes())?;
+        let mode = RENDER_MODES.iter().position(|&m| m == header.mode).expect("every mode is listed") as u8;
+        out.write_all(&[mode])?;
+        for value in [header.source_size.0, header.source_size.1, header.keyframe_interval.unwrap_or(0)] {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        let position = 50 + header.ramp.len() as u64;
+        Ok(Self {
+            out,
+            compressed: header.compressed,
+            keyframe_interval: header.keyframe_interval,
+            size: (header.cols, header.rows),
+            max_frame_len: header.max_frame_len(),
+            previous: None,
+            position,
+            elapsed: Duration::ZERO,
+            index: Vec::new(),
+        })
+    }
+
+    /// Append a text frame shown for `duration` after the previous ones.
+    /// Movies with a keyframe interval take grids from
+    /// [`write_grid`](Self::write_grid) instead.
+    pub fn write_frame(&mut self, text: &str, duration: Duration) -> Result<()> {
+        ensure!(self.keyframe_interval.is_none(), "this movie stores cell grids, not text");
+        let data = self.stored(text.as_bytes())?;
+        self.append(data, FRAME_TEXT, duration)
+    }
+
+    /// Append a converted frame shown for `duration`: as a keyframe, or a delta
+    /// against the previous grid when that is smaller, or as its ANSI text in
+    /// text movies. The grid must have the size given in the header.
+    pub fn write_grid(&mut self, grid: &AsciiGrid, duration: Duration) -> Result<()> {
+        let (cols, rows) = self.size;
+        ensure!(
+            (grid.width(), grid.rows) == self.size,
+            "frame {} is {}×{} cells, but the movie is {cols}×{rows}",
+            self.index.len(),
+            grid.width(),
+            grid.rows
+        );
+        let Some(interval) = self.keyframe_interval else {
+            return self.write_frame(&grid.to_ansi(), duration);
+        };
+        let keyframe = encode_keyframe(grid);
+        let delta = match &self.previous {
+            Some(previous) if !self.index.len().is_multiple_of(interval as usize) => encode_delta(previous, grid),
+            _ => None,
+        };
+        // A frame that changed almost everywhere is smaller as a keyframe
+        match delta.filter(|delta| delta.len() < keyframe.len()) {
+            Some(delta) => self.append(self.stored(&delta)?, FRAME_DELTA, duration)?,
+            None => self.append(self.stored(&keyframe)?, FRAME_KEY, duration)?,
+        }
+        self.previous = Some(grid.clone());
+        Ok(())
+    }
+
+    /// Frame bytes as stored in the file
+    fn stored(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        ensure!(payload.len() as u64 <= self.max_frame_len, "frame {} is larger than the movie's size allows", self.index.len());
+        Ok(match self.compressed {
+            true => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(payload)?;
+                encoder.finish()?
+            }
+            false => payload.to_vec(),
+        })
+    }
+
+    fn append(&mut self, data: Vec<u8>, kind: u8, duration: Duration) -> Result<()> {
+        self.out.write_all(&data)?;
+        self.index.push(IndexEntry {
+            offset: self.position,
+            length: u32::try_from(data.len()).context("frame larger than 4 GiB")?,
+            timestamp_us: self.elapsed.as_micros() as u64,
+            duration_us: duration.as_micros().min(u32::MAX as u128) as u32,
+            kind,
+        });
+        self.position += data.len() as u64;
+        self.elapsed += duration;
+        Ok(())
+    }
+
+    /// Frames written so far
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Write the index and trailer, returning the underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.out.write_all(&(self.index.len() as u64).to_le_bytes())?;
+        for entry in &self.index {
+            self.out.write_all(&entry.offset.to_le_bytes())?;
+            self.out.write_all(&entry.length.to_le_bytes())?;
+            self.out.write_all(&entry.timestamp_us.to_le_bytes())?;
+            self.out.write_all(&entry.duration_us.to_le_bytes())?;
+            self.out.write_all(&[entry.kind])?;
+        }
+        self.out.write_all(&self.position.to_le_bytes())?;
+        self.out.write_all(INDEX_MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Reads frames of a movie in any order
+pub struct MovieReader<R> {
+    input: R,
+    header: MovieHeader,
+    index: Vec<IndexEntry>,
+    /// Last grid decoded, so playing forward applies one delta per frame
+    decoded: Option<(usize, AsciiGrid)>,
+}
+
+impl MovieReader<BufReader<File>> {
+    /// Open the movie file at `path`
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("failed to read {}", path.display()))
+    }
+}
+
+impl<R: Read + Seek> MovieReader<R> {
+    /// Read the header and index
+    pub fn new(mut input: R) -> Result<Self> {
+        let mut fixed = [0u8; 37];
+        input.read_exact(&mut fixed).context("not an ASCII movie: file too short")?;
+        ensure!(&fixed[..8] == MAGIC, "not an ASCII movie");
+        let u16_at = |i: usize| u16::from_le_bytes([fixed[i], fixed[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(fixed[i..i + 4].try_into().unwrap());
+        let version = u16_at(8);
+        ensure!(version >= 1, "corrupt header: version 0");
+        ensure!(version <= VERSION, "ASCII movie version {version} is newer than this reader (version {VERSION})");
+        let color = match fixed[32] {
+            0 => ColorMode::None,
+            1 => ColorMode::Ansi16,
+            2 => ColorMode::Ansi256,
+            3 => ColorMode::Truecolor,
+            other => bail!("unknown color mode {other}"),
+        };
+        ensure!(u32_at(33) <= 1 << 16, "corrupt header: ramp too long");
+        let mut ramp = vec![0u8; u32_at(33) as usize];
+        input.read_exact(&mut ramp).context("truncated header")?;
+        let mut extra = [0u8; 13];
+        input.read_exact(&mut extra).context("truncated header")?;
+        let extra_u32 = |i: usize| u32::from_le_bytes(extra[i..i + 4].try_into().unwrap());
+        let header = MovieHeader {
+            cols: u32_at(12),
+            rows: u32_at(16),
+            cell_aspect: f32::from_bits(u32_at(20)),
+            fps: (u32_at(24) > 0 && u32_at(28) > 0).then(|| (u32_at(24), u32_at(28))),
+            color,
+            ramp: String::from_utf8(ramp).context("ramp is not UTF-8")?,
+            compressed: u16_at(10) & FLAG_DEFLATE != 0,
+            mode: *RENDER_MODES.get(extra[0] as usize).with_context(|| format!("unknown render mode {}", extra[0]))?,
+            source_size: (extra_u32(1), extra_u32(5)),
+            keyframe_interval: (extra_u32(9) > 0).then(|| extra_u32(9)),
+        };
+
+        let end = input.seek(SeekFrom::End(-16)).context("missing index")?;
+        let index_offset = read_u64(&mut input)?;
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        ensure!(&magic == INDEX_MAGIC, "missing index: the movie is truncated or still being written");
+        input.seek(SeekFrom::Start(index_offset))?;
+        let count = read_u64(&mut input)?;
+        ensure!(
+            index_offset.checked_add(8 + count.saturating_mul(25)) == Some(end),
+            "corrupt index"
+        );
+        let index = (0..count)
+            .map(|_| {
+                let entry = IndexEntry {
+                    offset: read_u64(&mut input)?,
+                    length: read_u32(&mut input)?,
+                    timestamp_us: read_u64(&mut input)?,
+                    duration_us: read_u32(&mut input)?,
+                    kind: read_u8(&mut input)?,
+                };
+                ensure!(entry.kind <= FRAME_DELTA, "corrupt index: unknown frame kind {}", entry.kind);
+                ensure!(entry.offset.checked_add(entry.length as u64).is_some_and(|e| e <= index_offset), "corrupt index");
+                Ok(entry)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { input, header, index, decoded: None })
+    }
+
+    pub fn header(&self) -> &MovieHeader {
+        &self.header
+    }
+
+    /// Number of frames
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Running time of the whole movie
+    pub fn duration(&self) -> Duration {
+        self.index.last().map_or(Duration::ZERO, |e| Duration::from_micros(e.timestamp_us + e.duration_us as u64))
+    }
+
+    /// When frame `i` is shown, from the start of the movie
+    pub fn timestamp(&self, i: usize) -> Duration {
+        Duration::from_micros(self.index[i].timestamp_us)
+    }
+
+    /// The frame on screen at `time`: the last one starting at or before it
+    pub fn seek(&self, time: Duration) -> usize {
+        let time = time.as_micros() as u64;
+        self.index.partition_point(|e| e.timestamp_us <= time).saturating_sub(1)
+    }
+
+    /// Read frame `i` as text with its duration
+    pub fn frame(&mut self, i: usize) -> Result<AsciiFrame> {
+        let entry = *self.index.get(i).with_context(|| format!("frame {i} is past the end of the movie"))?;
+        let delay = Duration::from_micros(entry.duration_us as u64);
+        if entry.kind != FRAME_TEXT {
+            return Ok(AsciiFrame { text: self.grid(i)?.to_ansi(), delay });
+        }
+        let text = String::from_utf8(self.payload(i)?).with_context(|| format!("frame {i} is not UTF-8"))?;
+        Ok(AsciiFrame { text, delay })
+    }
+
+    /// Rebuild the cell grid of frame `i` from the keyframe before it. Reading
+    /// frames in order applies a single delta each.
+    pub fn grid(&mut self, i: usize) -> Result<AsciiGrid> {
+        ensure!(i < self.index.len(), "frame {i} is past the end of the movie");
+        let key = (0..=i)
+            .rev()
+            .find(|&j| self.index[j].kind != FRAME_DELTA)
+            .with_context(|| format!("frame {i} has no keyframe before it"))?;
+        ensure!(self.index[key].kind == FRAME_KEY, "frame {key} is text, not a cell grid");
+
+        let (mut at, mut grid) = match self.decoded.take() {
+            Some((at, grid)) if (key..=i).contains(&at) => (at, grid),
+            _ => {
+                let metadata = GridMetadata { source_size: self.header.source_size, mode: self.header.mode, color: self.header.color };
+                let grid = decode_keyframe(&self.payload(key)?, metadata).with_context(|| format!("frame {key} is corrupt"))?;
+                (key, grid)
+            }
+        };
+        while at < i {
+            at += 1;
+            apply_delta(&mut grid, &self.payload(at)?).with_context(|| format!("frame {at} is corrupt"))?;
+        }
+        self.decoded = Some((i, grid.clone()));
+        Ok(grid)
+    }
+
+    /// Stored bytes of frame `i`, decompressed
+    fn payload(&mut self, i: usize) -> Result<Vec<u8>> {
+        let entry = self.index[i];
+        self.input.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = vec![0u8; entry.length as usize];
+        self.input.read_exact(&mut data)?;
+        // Frames can't hold more than the movie's size, so a corrupt one
+        // can't inflate without limit
+        let limit = self.header.max_frame_len();
+        if self.header.compressed {
+            let mut inflated = Vec::with_capacity(data.len() * 4);
+            DeflateDecoder::new(&data[..]).take(limit + 1).read_to_end(&mut inflated).with_context(|| format!("frame {i} is corrupt"))?;
+            data = inflated;
+        }
+        ensure!(data.len() as u64 <= limit, "frame {i} is larger than the movie's size allows");
+        Ok(data)
+    }
+}
+
+fn read_u8(input: &mut impl Read) -> Result<u8> {
+    let mut byte = [0u8; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn header(compressed: bool) -> MovieHeader {
+        MovieHeader {
+            cols: 3,
+            rows: 1,
+            cell_aspect: 2.0,
+            fps: Some((30000, 1001)),
+            color: ColorMode::Ansi256,
+            ramp: " .:█".to_string(),
+            compressed,
+            mode: RenderMode::Ramp,
+            source_size: (6, 2),
+            keyframe_interval: None,
+        }
+    }
+
+    fn grid(text: &str) -> AsciiGrid {
+        let cells = text.chars().map(|glyph| crate::grid::Cell { glyph, fg: None, bg: None, alpha: None }).collect();
+        let metadata = GridMetadata { source_size: (6, 2), mode: RenderMode::Ramp, color: ColorMode::Ansi256 };
+        AsciiGrid { cols: 3, rows: 1, cell_width: 1, cells, metadata }
+    }
+
+    #[test]
+    fn test_movie_round_trip() {
+        for compressed in [false, true] {
+            let mut writer = MovieWriter::new(Vec::new(), &header(compressed)).unwrap();
+            let frames = ["abc\n", "\x1b[38;5;196m█:.\x1b[0m\n", "xyz\n"];
+            for (text, ms) in frames.iter().zip([40, 40, 100]) {
+                writer.write_frame(text, Duration::from_millis(ms)).unwrap();
+            }
+            let data = writer.finish().unwrap();
+
+            let mut reader = MovieReader::new(Cursor::new(data)).unwrap();
+            assert_eq!(reader.header(), &header(compressed));
+            assert_eq!((reader.len(), reader.duration()), (3, Duration::from_millis(180)));
+            // Random access, out of order
+            assert_eq!(reader.frame(2).unwrap(), AsciiFrame { text: "xyz\n".into(), delay: Duration::from_millis(100) });
+            assert_eq!(reader.frame(1).unwrap().text, frames[1]);
+            assert_eq!(reader.timestamp(1), Duration::from_millis(40));
+            assert_eq!([0, 39, 40, 95, 500].map(|ms| reader.seek(Duration::from_millis(ms))), [0, 0, 1, 2, 2]);
+            assert!(reader.frame(3).is_err());
+        }
+    }
+
+    #[test]
+    fn test_movie_rejects_bad_files() {
+        assert!(MovieReader::new(Cursor::new(b"ASCIIMOX".repeat(8))).is_err());
+
+        let mut writer = MovieWriter::new(Vec::new(), &header(false)).unwrap();
+        writer.write_frame("abc\n", Duration::from_millis(40)).unwrap();
+        let data = writer.finish().unwrap();
+        // Cut off before the index was written
+        assert!(MovieReader::new(Cursor::new(&data[..data.len() - 30])).is_err());
+        let mut newer = data.clone();
+        newer[8] = VERSION as u8 + 1;
+        assert!(MovieReader::new(Cursor::new(newer)).is_err());
+
+        // A frame that inflates past what the header's size allows
+        let big = MovieHeader { cols: 100, ..header(true) };
+        let mut writer = MovieWriter::new(Vec::new(), &big).unwrap();
+        writer.write_frame(&"a".repeat(1000), Duration::from_millis(40)).unwrap();
+        let mut data = writer.finish().unwrap();
+        assert!(MovieWriter::new(Vec::new(), &header(true)).unwrap().write_frame(&"a".repeat(1000), Duration::ZERO).is_err());
+        data[12] = 3;
+        assert!(MovieReader::new(Cursor::new(data)).unwrap().frame(0).is_err());
+    }
+
+    #[test]
+    fn test_cell_movie_keyframes_and_deltas() {
+        let header = MovieHeader { keyframe_interval: Some(2), ..header(true) };
+        let grids = ["abc", "abd", "xbd", "xbd", "x:d"].map(grid);
+        let mut writer = MovieWriter::new(Vec::new(), &header).unwrap();
+        assert!(writer.write_frame("abc\n", Duration::from_millis(40)).is_err());
+        for g in &grids {
+            writer.write_grid(g, Duration::from_millis(40)).unwrap();
+        }
+        let mut reader = MovieReader::new(Cursor::new(writer.finish().unwrap())).unwrap();
+        assert_eq!(reader.header(), &header);
+        assert_eq!(reader.index.iter().map(|e| e.kind).collect::<Vec<_>>(), [1, 2, 1, 2, 1]);
+        // In order, then jumping back and forward across keyframes
+        for i in [0, 1, 2, 3, 4, 1, 3, 0] {
+            assert_eq!(reader.grid(i).unwrap(), grids[i]);
+        }
+        assert_eq!(reader.frame(3).unwrap().text, grids[3].to_ansi());
+
+        // Every grid has the header's size
+        let mut writer = MovieWriter::new(Vec::new(), &header).unwrap();
+        let tall = AsciiGrid { rows: 2, cells: [grids[0].cells.clone(), grids[1].cells.clone()].concat(), ..grids[0].clone() };
+        assert!(writer.write_grid(&tall, Duration::from_millis(40)).is_err());
+        assert!(writer.is_empty());
+    }
+}
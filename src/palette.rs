@@ -0,0 +1,134 @@
+//! Terminal color palettes and perceptual (CIELAB) nearest-color matching
+
+use std::sync::OnceLock;
+
+/// The basic 16 ANSI colors, using the xterm default values
+const ANSI16_RGB: [[u8; 3]; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// Channel levels of the xterm 6x6x6 color cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// RGB value of an xterm-256 palette index
+pub fn xterm_rgb(index: u8) -> [u8; 3] {
+    match index {
+        0..=15 => ANSI16_RGB[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            [
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            ]
+        }
+        232..=255 => {
+            let v = 8 + 10 * (index - 232);
+            [v, v, v]
+        }
+    }
+}
+
+/// Decode an sRGB channel value to linear light (0.0–1.0)
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Encode a linear-light value (0.0–1.0) back to an sRGB channel value
+pub fn linear_to_srgb(l: f32) -> u8 {
+    let l = l.clamp(0.0, 1.0);
+    let c = if l <= 0.0031308 { l * 12.92 } else { 1.055 * l.powf(1.0 / 2.4) - 0.055 };
+    (c * 255.0).round() as u8
+}
+
+/// Convert an sRGB color to CIELAB (D65 white point)
+pub fn srgb_to_lab(rgb: [u8; 3]) -> [f32; 3] {
+    fn f(t: f32) -> f32 {
+        if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 }
+    }
+
+    let [r, g, b] = rgb.map(srgb_to_linear);
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Index of the palette entry closest to `rgb` in CIELAB space
+fn nearest(rgb: [u8; 3], palette: &[(u8, [f32; 3])]) -> u8 {
+    let lab = srgb_to_lab(rgb);
+    let dist = |p: &[f32; 3]| {
+        (lab[0] - p[0]).powi(2) + (lab[1] - p[1]).powi(2) + (lab[2] - p[2]).powi(2)
+    };
+    palette
+        .iter()
+        .min_by(|a, b| dist(&a.1).total_cmp(&dist(&b.1)))
+        .map(|&(index, _)| index)
+        .unwrap_or(0)
+}
+
+/// Quantize to the xterm-256 color cube and grayscale ramp (indices 16-255).
+/// The 16 system colors are skipped because terminals theme them freely.
+pub fn nearest_ansi256(rgb: [u8; 3]) -> u8 {
+    static PALETTE: OnceLock<Vec<(u8, [f32; 3])>> = OnceLock::new();
+    let palette = PALETTE.get_or_init(|| {
+        (16..=255).map(|i| (i, srgb_to_lab(xterm_rgb(i)))).collect()
+    });
+    nearest(rgb, palette)
+}
+
+/// Quantize to the basic 16-color ANSI palette (indices 0-15)
+pub fn nearest_ansi16(rgb: [u8; 3]) -> u8 {
+    static PALETTE: OnceLock<Vec<(u8, [f32; 3])>> = OnceLock::new();
+    let palette = PALETTE.get_or_init(|| {
+        (0..16).map(|i| (i, srgb_to_lab(xterm_rgb(i)))).collect()
+    });
+    nearest(rgb, palette)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xterm_rgb() {
+        assert_eq!(xterm_rgb(16), [0, 0, 0]);
+        assert_eq!(xterm_rgb(196), [255, 0, 0]);
+        assert_eq!(xterm_rgb(231), [255, 255, 255]);
+        assert_eq!(xterm_rgb(232), [8, 8, 8]);
+    }
+
+    #[test]
+    fn test_nearest_colors() {
+        assert_eq!(nearest_ansi256([255, 0, 0]), 196);
+        assert_eq!(nearest_ansi256([128, 128, 128]), 244);
+        assert_eq!(nearest_ansi16([250, 5, 5]), 9);
+        assert_eq!(nearest_ansi16([0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn test_srgb_linear_round_trip() {
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(128) - 0.2158).abs() < 0.001);
+        assert!((0..=255).all(|c| linear_to_srgb(srgb_to_linear(c)) == c));
+    }
+}
@@ -0,0 +1,286 @@
+//! Terminal playback of converted frames
+
+use anyhow::Result;
+use crate::animation::AsciiFrame;
+use crate::ansi::{push_bg, push_fg, push_skip, AnsiColor, RESET};
+use crate::delta::{push_row_runs, Run};
+use crate::grid::Cell;
+use crossterm::{
+    cursor::{Hide, Show},
+    execute, queue,
+    terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::io::{stdout, Stdout, Write};
+use std::time::{Duration, Instant};
+use unicode_width::UnicodeWidthChar;
+
+/// SGR reset followed by reverse video
+const INVERT_AFTER_RESET: &str = "\x1b[0;7m";
+
+/// A screen position nothing was drawn at, which shows as blank
+const UNDRAWN: Cell = Cell { glyph: ' ', fg: None, bg: None, alpha: Some(0) };
+
+/// Stands for the right half of a double-width glyph
+const WIDE_TAIL: char = '\0';
+
+/// What playback wrote to the terminal
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlaybackStats {
+    pub frames: usize,
+    /// Bytes written, escape sequences included
+    pub bytes: u64,
+    pub elapsed: Duration,
+}
+
+impl PlaybackStats {
+    /// Average output rate over the whole playback
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64().max(1e-3)
+    }
+}
+
+/// Play frames on the alternate screen, each for its delay. Only the cells
+/// that changed since the previous frame are redrawn, and each frame is
+/// wrapped in synchronized output (DEC mode 2026), which terminals without
+/// support ignore. With `invert`, foreground and background are swapped
+/// (reverse video) for light-background terminals. The screen is restored
+/// even if a frame fails to load.
+pub fn play(frames: impl IntoIterator<Item = Result<AsciiFrame>>, invert: bool) -> Result<PlaybackStats> {
+    let mut out = stdout();
+    execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+    let result = play_frames(&mut out, frames, invert);
+    execute!(out, Show, LeaveAlternateScreen)?;
+    result
+}
+
+fn play_frames(out: &mut Stdout, frames: impl IntoIterator<Item = Result<AsciiFrame>>, invert: bool) -> Result<PlaybackStats> {
+    let start = Instant::now();
+    let mut deadline = start;
+    let mut screen = Screen { rows: Vec::new(), invert };
+    let mut stats = PlaybackStats::default();
+    let mut buffer = Vec::new();
+    for frame in frames {
+        let frame = frame?;
+        buffer.clear();
+        screen.draw(&frame.text, &mut buffer)?;
+        out.write_all(&buffer)?;
+        out.flush()?;
+        stats.frames += 1;
+        stats.bytes += buffer.len() as u64;
+
+        // Keep the cadence from drifting with the time spent drawing
+        deadline += frame.delay;
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+    stats.elapsed = start.elapsed();
+    Ok(stats)
+}
+
+/// What is on the terminal: one cell per column, as of the last frame drawn
+struct Screen {
+    rows: Vec<Vec<Cell>>,
+    invert: bool,
+}
+
+impl Screen {
+    /// Write the escapes that turn the screen into `text` to `out`
+    fn draw(&mut self, text: &str, out: &mut Vec<u8>) -> Result<()> {
+        let next = parse_ansi(text);
+        let mut runs = Vec::new();
+        for row in 0..self.rows.len().max(next.len()) {
+            let prev = self.rows.get(row).map_or(&[][..], |r| &r[..]);
+            let cells = next.get(row).map_or(&[][..], |r| &r[..]);
+            push_row_runs(&mut runs, row as u32, prev, cells, Some(&UNDRAWN));
+        }
+
+        queue!(out, BeginSynchronizedUpdate)?;
+        let mut pen = Pen::default();
+        let mut cursor = None;
+        let mut escapes = String::new();
+        for Run { row, col, len } in runs {
+            escapes.clear();
+            match cursor {
+                Some((r, c)) if r == row && c <= col => push_skip(&mut escapes, (col - c) as usize),
+                _ => escapes.push_str(&format!("\x1b[{};{}H", row + 1, col + 1)),
+            }
+            out.extend_from_slice(escapes.as_bytes());
+            let cells = next.get(row as usize).map_or(&[][..], |r| &r[..]);
+            // Where the terminal leaves the cursor: a wide glyph advances it by
+            // two columns even when the run ends before its right half
+            let mut end = col;
+            for col in col..col + len {
+                let cell = cells.get(col as usize).unwrap_or(&UNDRAWN);
+                if cell.glyph == WIDE_TAIL {
+                    continue;
+                }
+                let style = match cell.is_empty() {
+                    true => Pen::default(),
+                    false => Pen { fg: cell.fg, bg: cell.bg, invert: self.invert },
+                };
+                if style != pen {
+                    escapes.clear();
+                    pen.change_to(style, &mut escapes);
+                    out.extend_from_slice(escapes.as_bytes());
+                }
+                let mut utf8 = [0; 4];
+                out.extend_from_slice(cell.glyph.encode_utf8(&mut utf8).as_bytes());
+                end = col + cell.glyph.width().unwrap_or(1).max(1) as u32;
+            }
+            cursor = Some((row, end));
+        }
+        if pen != Pen::default() {
+            out.extend_from_slice(RESET.as_bytes());
+        }
+        queue!(out, EndSynchronizedUpdate)?;
+        self.rows = next;
+        Ok(())
+    }
+}
+
+/// Colors and reverse video the terminal prints with
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Pen {
+    fg: Option<AnsiColor>,
+    bg: Option<AnsiColor>,
+    invert: bool,
+}
+
+impl Pen {
+    /// Append the escapes that switch to `to`, resetting only when a color or
+    /// reverse video has to be turned off
+    fn change_to(&mut self, to: Pen, out: &mut String) {
+        if (self.fg.is_some() && to.fg.is_none()) || (self.bg.is_some() && to.bg.is_none()) || self.invert != to.invert {
+            out.push_str(if to.invert { INVERT_AFTER_RESET } else { RESET });
+            *self = Pen { invert: to.invert, ..Pen::default() };
+        }
+        if let Some(fg) = to.fg.filter(|&fg| self.fg != Some(fg)) {
+            push_fg(out, fg);
+        }
+        if let Some(bg) = to.bg.filter(|&bg| self.bg != Some(bg)) {
+            push_bg(out, bg);
+        }
+        *self = to;
+    }
+}
+
+/// Lay out frame text as written by [`crate::AsciiGrid::to_ansi`]: one cell
+/// per terminal column, with the colors in effect when it was printed. Columns
+/// skipped by cursor movement are left undrawn.
+fn parse_ansi(text: &str) -> Vec<Vec<Cell>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let (mut fg, mut bg) = (None, None);
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => rows.push(std::mem::take(&mut row)),
+            '\x1b' if chars.peek() == Some(&'[') => {
+                chars.next();
+                let mut params = String::new();
+                let command = chars.by_ref().find(|c| match c {
+                    '@'..='~' => true,
+                    _ => {
+                        params.push(*c);
+                        false
+                    }
+                });
+                match command {
+                    Some('m') => apply_sgr(&params, &mut fg, &mut bg),
+                    Some('C') => row.extend(std::iter::repeat_n(UNDRAWN, params.parse().unwrap_or(1))),
+                    _ => {}
+                }
+            }
+            c if c.is_control() => {}
+            glyph => {
+                row.push(Cell { glyph, fg, bg, alpha: None });
+                for _ in 1..glyph.width().unwrap_or(1) {
+                    row.push(Cell { glyph: WIDE_TAIL, fg, bg, alpha: None });
+                }
+            }
+        }
+    }
+    if !row.is_empty() {
+        rows.push(row);
+    }
+    rows
+}
+
+/// Update the colors in effect with an SGR escape's parameters
+fn apply_sgr(params: &str, fg: &mut Option<AnsiColor>, bg: &mut Option<AnsiColor>) {
+    let mut params = params.split(';').map(|p| p.parse::<u8>().unwrap_or(0));
+    while let Some(param) = params.next() {
+        match param {
+            0 => (*fg, *bg) = (None, None),
+            30..=37 => *fg = Some(AnsiColor::Indexed(param - 30)),
+            90..=97 => *fg = Some(AnsiColor::Indexed(param - 90 + 8)),
+            40..=47 => *bg = Some(AnsiColor::Indexed(param - 40)),
+            100..=107 => *bg = Some(AnsiColor::Indexed(param - 100 + 8)),
+            39 => *fg = None,
+            49 => *bg = None,
+            38 | 48 => {
+                let color = match params.next() {
+                    Some(5) => params.next().map(AnsiColor::Indexed),
+                    Some(2) => Some(AnsiColor::Rgb([0; 3].map(|_| params.next().unwrap_or(0)))),
+                    _ => None,
+                };
+                *(if param == 38 { &mut *fg } else { &mut *bg }) = color;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ansi::ColorMode;
+    use crate::grid::{AsciiGrid, GridMetadata};
+    use crate::image_processing::RenderMode;
+
+    #[test]
+    fn test_parse_ansi_round_trip() {
+        let cells = vec![
+            Cell { glyph: 'a', fg: Some(AnsiColor::Rgb([1, 2, 3])), bg: None, alpha: None },
+            Cell { glyph: 'b', fg: Some(AnsiColor::Indexed(9)), bg: Some(AnsiColor::Indexed(200)), alpha: None },
+            Cell { glyph: 'c', fg: None, bg: None, alpha: Some(0) },
+            Cell { glyph: 'd', fg: Some(AnsiColor::Indexed(3)), bg: Some(AnsiColor::Indexed(200)), alpha: None },
+        ];
+        let metadata = GridMetadata { source_size: (4, 1), mode: RenderMode::Ramp, color: ColorMode::Truecolor };
+        let grid = AsciiGrid { cols: 4, rows: 1, cell_width: 1, cells: cells.clone(), metadata };
+        assert_eq!(parse_ansi(&grid.to_ansi()), [vec![cells[0], cells[1], UNDRAWN, cells[3]]]);
+        // Wide glyphs take two columns
+        assert_eq!(parse_ansi("日x\n")[0].len(), 3);
+    }
+
+    #[test]
+    fn test_draws_only_changed_cells() {
+        let mut screen = Screen { rows: Vec::new(), invert: false };
+        let mut draw = |text: &str| {
+            let mut out = Vec::new();
+            screen.draw(text, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(draw("abcdefgh\n12345678\n"), "\x1b[?2026h\x1b[1;1Habcdefgh\x1b[2;1H12345678\x1b[?2026l");
+        assert_eq!(draw("abcdefgX\n12\x1b[31m3\x1b[0m45678\n"), "\x1b[?2026h\x1b[1;8HX\x1b[2;3H\x1b[31m3\x1b[0m\x1b[?2026l");
+        // Runs further along the same row are reached by moving the cursor forward
+        assert_eq!(draw("AbcdefgH\n12\x1b[31m3\x1b[0m45678\n"), "\x1b[?2026h\x1b[1;1HA\x1b[6CH\x1b[?2026l");
+        // Shorter frames blank out what the previous one drew
+        assert_eq!(draw("AbcdefgH\n"), "\x1b[?2026h\x1b[2;1H        \x1b[?2026l");
+    }
+
+    #[test]
+    fn test_skips_past_wide_glyphs() {
+        let mut screen = Screen { rows: Vec::new(), invert: false };
+        let mut draw = |text: &str| {
+            let mut out = Vec::new();
+            screen.draw(text, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        draw("中中....x\n");
+        // '日' moves the cursor two columns, so 'y' is six further on
+        assert_eq!(draw("日中....y\n"), "\x1b[?2026h\x1b[1;1H日\x1b[6Cy\x1b[?2026l");
+    }
+}
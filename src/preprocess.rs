@@ -0,0 +1,313 @@
+//! Geometric preprocessing before conversion: EXIF auto-orientation,
+//! rotation, flips and cropping
+
+use anyhow::{bail, Context, Result};
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageReader, Rgba, RgbaImage};
+use std::path::Path;
+
+/// A crop coordinate or length, in pixels or as a percentage of the image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Length {
+    fn resolve(self, full: u32) -> u32 {
+        match self {
+            Length::Pixels(p) => p,
+            Length::Percent(p) => (full as f32 * p / 100.0).round() as u32,
+        }
+    }
+
+    fn scaled(self, factor: f64) -> Self {
+        match self {
+            Length::Pixels(p) => Length::Pixels((p as f64 * factor).round() as u32),
+            percent => percent,
+        }
+    }
+}
+
+/// Crop rectangle, clamped to the image when applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crop {
+    pub x: Length,
+    pub y: Length,
+    pub width: Length,
+    pub height: Length,
+}
+
+impl Crop {
+    /// The rectangle `(x, y, width, height)` in pixels on a `w`×`h` image
+    pub fn resolve(&self, (w, h): (u32, u32)) -> Result<(u32, u32, u32, u32)> {
+        let (x, y) = (self.x.resolve(w).min(w), self.y.resolve(h).min(h));
+        let (cw, ch) = (self.width.resolve(w).min(w - x), self.height.resolve(h).min(h - y));
+        if cw == 0 || ch == 0 {
+            bail!("crop leaves nothing of the {w}×{h} image");
+        }
+        Ok((x, y, cw, ch))
+    }
+}
+
+/// Parse a crop rectangle `x,y,width,height`, each in pixels (`120`) or as a
+/// percentage of the image (`10%`)
+pub fn parse_crop(spec: &str) -> Result<Crop> {
+    let parts: Vec<&str> = spec.split(',').map(str::trim).collect();
+    let [x, y, width, height] = parts.as_slice() else {
+        bail!("Invalid crop {spec:?}: expected x,y,width,height (e.g. 100,50,640,480 or 10%,10%,80%,80%)");
+    };
+    let length = |part: &str| -> Result<Length> {
+        Ok(match part.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<f32>() {
+                Ok(p) if (0.0..=100.0).contains(&p) => Length::Percent(p),
+                _ => bail!("Invalid crop percentage {part:?} (0% to 100%)"),
+            },
+            None => Length::Pixels(part.parse().map_err(|_| anyhow::anyhow!("Invalid crop value {part:?}"))?),
+        })
+    };
+    Ok(Crop { x: length(x)?, y: length(y)?, width: length(width)?, height: length(height)? })
+}
+
+/// Geometric changes applied to an image before conversion, in order:
+/// EXIF orientation (when decoding), rotation, flips, then the crop, whose
+/// coordinates refer to the rotated and flipped image.
+///
+/// ```
+/// use rust_ascii::{parse_crop, Preprocess};
+/// let preprocess = Preprocess::new().rotate(90.0).flip_horizontal(true).crop(parse_crop("10%,0,80%,100%").unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preprocess {
+    /// Turn images upright according to their EXIF orientation
+    pub auto_orient: bool,
+    /// Clockwise rotation in degrees; other than multiples of 90° the image
+    /// grows to fit and the uncovered corners are transparent
+    pub rotate: f32,
+    /// Mirror left to right
+    pub flip_horizontal: bool,
+    /// Mirror top to bottom
+    pub flip_vertical: bool,
+    pub crop: Option<Crop>,
+}
+
+impl Default for Preprocess {
+    fn default() -> Self {
+        Self { auto_orient: true, rotate: 0.0, flip_horizontal: false, flip_vertical: false, crop: None }
+    }
+}
+
+impl Preprocess {
+    /// Auto-orientation only
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn auto_orient(mut self, on: bool) -> Self {
+        self.auto_orient = on;
+        self
+    }
+
+    pub fn rotate(mut self, degrees: f32) -> Self {
+        self.rotate = degrees;
+        self
+    }
+
+    pub fn flip_horizontal(mut self, on: bool) -> Self {
+        self.flip_horizontal = on;
+        self
+    }
+
+    pub fn flip_vertical(mut self, on: bool) -> Self {
+        self.flip_vertical = on;
+        self
+    }
+
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+
+    /// Decode the image at `path` and apply the preprocessing
+    pub fn open(&self, path: &Path) -> Result<DynamicImage> {
+        let display = path.display();
+        let mut decoder = ImageReader::open(path)
+            .with_context(|| format!("failed to open {display}"))?
+            .with_guessed_format()?
+            .into_decoder()
+            .with_context(|| format!("failed to decode {display}"))?;
+        let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+        let mut img = DynamicImage::from_decoder(decoder).with_context(|| format!("failed to decode {display}"))?;
+        if self.auto_orient {
+            img.apply_orientation(orientation);
+        }
+        self.apply(img)
+    }
+
+    /// Rotate, flip and crop an already decoded (and oriented) image
+    pub fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        let img = match self.quarter_turns()? {
+            Some(0) => img,
+            Some(1) => img.rotate90(),
+            Some(2) => img.rotate180(),
+            Some(_) => img.rotate270(),
+            None => rotate_any(&img, self.rotate),
+        };
+        let img = if self.flip_horizontal { img.fliph() } else { img };
+        let img = if self.flip_vertical { img.flipv() } else { img };
+        match &self.crop {
+            Some(crop) => {
+                let (x, y, w, h) = crop.resolve(img.dimensions())?;
+                Ok(img.crop_imm(x, y, w, h))
+            }
+            None => Ok(img),
+        }
+    }
+
+    /// Size of a `w`×`h` (already oriented) image after preprocessing
+    pub fn output_dimensions(&self, (w, h): (u32, u32)) -> Result<(u32, u32)> {
+        let (w, h) = match self.quarter_turns()? {
+            Some(turns) if turns % 2 == 1 => (h, w),
+            Some(_) => (w, h),
+            None => rotated_size((w, h), self.rotate),
+        };
+        match &self.crop {
+            Some(crop) => crop.resolve((w, h)).map(|(_, _, w, h)| (w, h)),
+            None => Ok((w, h)),
+        }
+    }
+
+    /// The same preprocessing for the image scaled by `factor`: pixel crop
+    /// coordinates are scaled with it
+    pub fn scaled(&self, factor: f64) -> Self {
+        let crop = self.crop.map(|c| Crop {
+            x: c.x.scaled(factor),
+            y: c.y.scaled(factor),
+            width: c.width.scaled(factor),
+            height: c.height.scaled(factor),
+        });
+        Self { crop, ..self.clone() }
+    }
+
+    /// The rotation as a number of clockwise quarter turns, or `None` for
+    /// other angles
+    fn quarter_turns(&self) -> Result<Option<u32>> {
+        if !self.rotate.is_finite() {
+            bail!("rotation must be a finite number of degrees");
+        }
+        let degrees = self.rotate.rem_euclid(360.0);
+        let turns = (degrees / 90.0).round();
+        Ok(((degrees - turns * 90.0).abs() < 1e-3).then_some(turns as u32 % 4))
+    }
+}
+
+/// EXIF orientation of the image at `path`, read without decoding its pixels
+pub(crate) fn exif_orientation(path: &Path) -> Result<Orientation> {
+    let mut decoder = ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+    Ok(decoder.orientation().unwrap_or(Orientation::NoTransforms))
+}
+
+/// Whether applying `orientation` swaps width and height
+pub(crate) fn swaps_dimensions(orientation: Orientation) -> bool {
+    matches!(
+        orientation,
+        Orientation::Rotate90 | Orientation::Rotate270 | Orientation::Rotate90FlipH | Orientation::Rotate270FlipH
+    )
+}
+
+/// Bounding box of a `w`×`h` image rotated by `degrees`
+fn rotated_size((w, h): (u32, u32), degrees: f32) -> (u32, u32) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (sin, cos) = (sin.abs(), cos.abs());
+    // Tolerate float error so right angles do not grow by a pixel
+    let bound = |a: f32| (a - 1e-3).ceil().max(1.0) as u32;
+    (bound(w as f32 * cos + h as f32 * sin), bound(w as f32 * sin + h as f32 * cos))
+}
+
+/// Rotate clockwise by any angle with bilinear sampling; the canvas grows to
+/// the rotated bounding box and uncovered pixels are transparent
+fn rotate_any(img: &DynamicImage, degrees: f32) -> DynamicImage {
+    let src = img.to_rgba8();
+    let (w, h) = src.dimensions();
+    let (out_w, out_h) = rotated_size((w, h), degrees);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let (ox, oy) = (out_w as f32 / 2.0, out_h as f32 / 2.0);
+
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(out_w, out_h, |x, y| {
+        // Rotate the output pixel's center back onto the source
+        let (dx, dy) = (x as f32 + 0.5 - ox, y as f32 + 0.5 - oy);
+        let sx = dx * cos + dy * sin + cx - 0.5;
+        let sy = -dx * sin + dy * cos + cy - 0.5;
+        bilinear(&src, sx, sy)
+    }))
+}
+
+/// Bilinear sample at pixel coordinates, weighting colors by alpha so the
+/// transparent surroundings do not darken the edges
+fn bilinear(src: &RgbaImage, sx: f32, sy: f32) -> Rgba<u8> {
+    let (x0, y0) = (sx.floor(), sy.floor());
+    let (fx, fy) = (sx - x0, sy - y0);
+    let mut acc = [0.0f32; 4];
+    for (dx, dy, weight) in [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)] {
+        let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+        if weight <= 0.0 || px < 0 || py < 0 || px >= src.width() as i64 || py >= src.height() as i64 {
+            continue;
+        }
+        let p = src.get_pixel(px as u32, py as u32);
+        let a = p[3] as f32 * weight;
+        for c in 0..3 {
+            acc[c] += p[c] as f32 * a;
+        }
+        acc[3] += a;
+    }
+    if acc[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let color = |c: f32| (c / acc[3]).round().clamp(0.0, 255.0) as u8;
+    Rgba([color(acc[0]), color(acc[1]), color(acc[2]), acc[3].round().clamp(0.0, 255.0) as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+
+    #[test]
+    fn test_parse_crop() {
+        let crop = parse_crop("10,20%, 30 ,50%").unwrap();
+        assert_eq!(crop.x, Length::Pixels(10));
+        assert_eq!(crop.height, Length::Percent(50.0));
+        assert_eq!(crop.resolve((200, 100)).unwrap(), (10, 20, 30, 50));
+        // Clamped to the image
+        assert_eq!(parse_crop("150,0,100,100%").unwrap().resolve((200, 100)).unwrap(), (150, 0, 50, 100));
+        assert!(parse_crop("200,0,10,10").unwrap().resolve((200, 100)).is_err());
+        assert!(parse_crop("1,2,3").is_err());
+        assert!(parse_crop("0,0,120%,50%").is_err());
+    }
+
+    #[test]
+    fn test_preprocess_order() {
+        // 4×2 gradient: rotate to 2×4, flip, then crop the top half
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(4, 2, |x, y| Luma([(y * 4 + x) as u8])));
+        let preprocess = Preprocess::new()
+            .rotate(-270.0)
+            .flip_vertical(true)
+            .crop(parse_crop("0,0,100%,50%").unwrap());
+        assert_eq!(preprocess.output_dimensions((4, 2)).unwrap(), (2, 2));
+        let out = preprocess.apply(img).unwrap().to_luma8();
+        assert_eq!(out.as_raw(), &vec![7, 3, 6, 2]);
+    }
+
+    #[test]
+    fn test_rotate_any() {
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(6, 4, |x, y| Luma([(y * 40 + x * 5) as u8])));
+        // Bilinear rotation by a right angle lands on pixel centers
+        let rotated = rotate_any(&img, 90.000_01).to_luma8();
+        assert_eq!(rotated, img.rotate90().to_luma8());
+
+        let tilted = rotate_any(&img, 30.0);
+        assert_eq!(tilted.dimensions(), Preprocess::new().rotate(30.0).output_dimensions((6, 4)).unwrap());
+        assert_eq!(tilted.to_rgba8().get_pixel(0, 0)[3], 0);
+    }
+}
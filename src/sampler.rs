@@ -0,0 +1,246 @@
+//! Resampling of the source image onto the character grid
+
+use anyhow::Result;
+use crate::area::{AreaSampler, Pooling};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Geometry of the sample grid an image is resampled onto.
+///
+/// Sample `(x, y)` covers source pixels `x / scale_x .. (x + 1) / scale_x`
+/// horizontally and `y / scale_y * cell_aspect .. (y + 1) / scale_y * cell_aspect`
+/// vertically, where `cell_aspect` stretches rows for cells that are taller
+/// than they are wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    /// Number of samples across
+    pub width: u32,
+    /// Number of samples down
+    pub height: u32,
+    /// Samples per source pixel horizontally
+    pub scale_x: f32,
+    /// Samples per source pixel vertically, before the cell aspect correction
+    pub scale_y: f32,
+    /// Height/width ratio of one sample's footprint relative to `scale_x`
+    pub cell_aspect: f32,
+}
+
+impl Grid {
+    /// Source x coordinate of the center of sample column `x`
+    pub fn source_x(&self, x: u32) -> f32 {
+        (x as f32 + 0.5) / self.scale_x
+    }
+
+    /// Source y coordinate of the center of sample row `y`
+    pub fn source_y(&self, y: u32) -> f32 {
+        (y as f32 + 0.5) / self.scale_y * self.cell_aspect
+    }
+}
+
+/// Resamples a source image onto a [`Grid`].
+///
+/// Implementations must return an image of exactly `grid.width`×`grid.height`.
+/// The result is converted to luminance and RGB afterwards, so any color type
+/// works; keeping the input's color type (e.g. `Rgb32F` when resampling in
+/// linear light) avoids losing precision.
+pub trait Sampler: Send + Sync + fmt::Debug {
+    fn sample(&self, img: &DynamicImage, grid: &Grid) -> DynamicImage;
+}
+
+/// How the source image is resampled onto the character grid
+#[derive(Debug, Clone, Default)]
+pub enum Resizer {
+    /// Nearest-neighbor filter
+    Nearest,
+    /// Bilinear filter (default)
+    #[default]
+    Triangle,
+    /// Lanczos filter with a window of 3
+    Lanczos3,
+    /// One source pixel read at each sample's center
+    Pixel,
+    /// Every source pixel becomes one sample; the column count is ignored
+    OneToOne,
+    /// All source pixels under each sample's footprint, averaged or pooled
+    Area(Pooling),
+    /// A caller-provided sampler
+    Custom(Arc<dyn Sampler>),
+}
+
+/// Select the resizer based on its CLI name
+pub fn select_resizer(name: &str) -> Result<Resizer> {
+    Ok(match name {
+        "nearest" => Resizer::Nearest,
+        "triangle" => Resizer::Triangle,
+        "lanczos3" => Resizer::Lanczos3,
+        "pixel" => Resizer::Pixel,
+        "1to1" => Resizer::OneToOne,
+        "area" => Resizer::Area(Pooling::Mean),
+        "min" => Resizer::Area(Pooling::Min),
+        "max" => Resizer::Area(Pooling::Max),
+        "median" => Resizer::Area(Pooling::Median),
+        other => anyhow::bail!(
+            "unknown resizer: {other}. Available options: nearest, triangle, lanczos3, pixel, 1to1, area, min, max, median"
+        ),
+    })
+}
+
+impl FromStr for Resizer {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        select_resizer(name)
+    }
+}
+
+impl fmt::Display for Resizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resizer::Nearest => f.write_str("nearest"),
+            Resizer::Triangle => f.write_str("triangle"),
+            Resizer::Lanczos3 => f.write_str("lanczos3"),
+            Resizer::Pixel => f.write_str("pixel"),
+            Resizer::OneToOne => f.write_str("1to1"),
+            Resizer::Area(Pooling::Mean) => f.write_str("area"),
+            Resizer::Area(Pooling::Min) => f.write_str("min"),
+            Resizer::Area(Pooling::Max) => f.write_str("max"),
+            Resizer::Area(Pooling::Median) => f.write_str("median"),
+            Resizer::Custom(sampler) => write!(f, "{sampler:?}"),
+        }
+    }
+}
+
+impl Resizer {
+    /// The `image` crate filter behind this resizer, if it is a filtering one
+    pub fn filter(&self) -> Option<FilterType> {
+        match self {
+            Resizer::Nearest => Some(FilterType::Nearest),
+            Resizer::Triangle => Some(FilterType::Triangle),
+            Resizer::Lanczos3 => Some(FilterType::Lanczos3),
+            _ => None,
+        }
+    }
+}
+
+impl Resizer {
+    /// Whether [`sample_rows`](Self::sample_rows) reads only the source rows
+    /// under the requested grid rows
+    pub(crate) fn samples_rows(&self) -> bool {
+        matches!(self, Resizer::Pixel | Resizer::OneToOne | Resizer::Area(_))
+    }
+
+    /// Sample grid rows `rows` of the `(x, y, width, height)` region of `img`.
+    /// Resizers that can't sample part of the grid sample all of the region
+    /// and keep those rows.
+    pub(crate) fn sample_rows(&self, img: &DynamicImage, region: (u32, u32, u32, u32), grid: &Grid, rows: Range<u32>) -> DynamicImage {
+        let (x0, y0, w, h) = region;
+        let band = Grid { height: rows.len() as u32, ..*grid };
+        let first = rows.start;
+        match self {
+            Resizer::Pixel => pick(img, &band, |x, y| {
+                Some((x0 + (grid.source_x(x) as u32).min(w - 1), y0 + (grid.source_y(y + first) as u32).min(h - 1)))
+            }),
+            Resizer::OneToOne => pick(img, &band, |x, y| (x < w && y + first < h).then_some((x0 + x, y0 + y + first))),
+            Resizer::Area(pooling) => AreaSampler { pooling: *pooling }.sample_rows(img, region, grid, rows),
+            _ => {
+                let region = match region == (0, 0, img.width(), img.height()) {
+                    true => Cow::Borrowed(img),
+                    false => Cow::Owned(img.crop_imm(x0, y0, w, h)),
+                };
+                self.sample(&region, grid).crop_imm(0, first, grid.width, band.height)
+            }
+        }
+    }
+}
+
+impl Sampler for Resizer {
+    fn sample(&self, img: &DynamicImage, grid: &Grid) -> DynamicImage {
+        match self {
+            Resizer::Pixel => PointSampler.sample(img, grid),
+            Resizer::OneToOne => pick(img, grid, |x, y| (x < img.width() && y < img.height()).then_some((x, y))),
+            Resizer::Area(pooling) => AreaSampler { pooling: *pooling }.sample(img, grid),
+            Resizer::Custom(sampler) => sampler.sample(img, grid),
+            filtering => img.resize_exact(grid.width, grid.height, filtering.filter().unwrap_or(FilterType::Triangle)),
+        }
+    }
+}
+
+/// Reads the one source pixel under each sample's center
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PointSampler;
+
+impl Sampler for PointSampler {
+    fn sample(&self, img: &DynamicImage, grid: &Grid) -> DynamicImage {
+        let (w, h) = img.dimensions();
+        pick(img, grid, |x, y| {
+            Some(((grid.source_x(x) as u32).min(w - 1), (grid.source_y(y) as u32).min(h - 1)))
+        })
+    }
+}
+
+/// Build a grid-sized image by copying the source pixel `source` returns for
+/// each sample, or black where it returns `None`. The source's color type is
+/// kept, so a grayscale image is not widened to RGBA on the way.
+fn pick(img: &DynamicImage, grid: &Grid, source: impl Fn(u32, u32) -> Option<(u32, u32)>) -> DynamicImage {
+    fn copy<P: Pixel>(
+        buf: &ImageBuffer<P, Vec<P::Subpixel>>,
+        grid: &Grid,
+        source: &impl Fn(u32, u32) -> Option<(u32, u32)>,
+        black: P,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        ImageBuffer::from_fn(grid.width, grid.height, |x, y| source(x, y).map_or(black, |(sx, sy)| *buf.get_pixel(sx, sy)))
+    }
+
+    match img {
+        DynamicImage::ImageLuma8(buf) => DynamicImage::ImageLuma8(copy(buf, grid, &source, Luma([0]))),
+        DynamicImage::ImageLumaA8(buf) => DynamicImage::ImageLumaA8(copy(buf, grid, &source, LumaA([0, u8::MAX]))),
+        DynamicImage::ImageRgb8(buf) => DynamicImage::ImageRgb8(copy(buf, grid, &source, Rgb([0; 3]))),
+        DynamicImage::ImageRgba8(buf) => DynamicImage::ImageRgba8(copy(buf, grid, &source, Rgba([0, 0, 0, u8::MAX]))),
+        DynamicImage::ImageLuma16(buf) => DynamicImage::ImageLuma16(copy(buf, grid, &source, Luma([0]))),
+        DynamicImage::ImageLumaA16(buf) => DynamicImage::ImageLumaA16(copy(buf, grid, &source, LumaA([0, u16::MAX]))),
+        DynamicImage::ImageRgb16(buf) => DynamicImage::ImageRgb16(copy(buf, grid, &source, Rgb([0; 3]))),
+        DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba16(copy(buf, grid, &source, Rgba([0, 0, 0, u16::MAX]))),
+        DynamicImage::ImageRgb32F(buf) => DynamicImage::ImageRgb32F(copy(buf, grid, &source, Rgb([0.0; 3]))),
+        DynamicImage::ImageRgba32F(buf) => DynamicImage::ImageRgba32F(copy(buf, grid, &source, Rgba([0.0, 0.0, 0.0, 1.0]))),
+        _ => DynamicImage::ImageRgba8(ImageBuffer::from_fn(grid.width, grid.height, |x, y| {
+            source(x, y).map_or(Rgba([0, 0, 0, u8::MAX]), |(sx, sy)| img.get_pixel(sx, sy))
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    #[test]
+    fn test_select_resizer() {
+        assert!(matches!(select_resizer("triangle").unwrap(), Resizer::Triangle));
+        assert!(matches!("1to1".parse::<Resizer>().unwrap(), Resizer::OneToOne));
+        assert!(select_resizer("invalid").is_err());
+        for name in ["nearest", "triangle", "lanczos3", "pixel", "1to1", "area", "min", "max", "median"] {
+            assert_eq!(select_resizer(name).unwrap().to_string(), name);
+        }
+    }
+
+    #[test]
+    fn test_custom_sampler() {
+        #[derive(Debug)]
+        struct Flat;
+        impl Sampler for Flat {
+            fn sample(&self, _: &DynamicImage, grid: &Grid) -> DynamicImage {
+                DynamicImage::ImageLuma8(GrayImage::from_pixel(grid.width, grid.height, Luma([7])))
+            }
+        }
+
+        let grid = Grid { width: 3, height: 2, scale_x: 1.0, scale_y: 1.0, cell_aspect: 1.0 };
+        let img = DynamicImage::ImageLuma8(GrayImage::new(10, 10));
+        let sampled = Resizer::Custom(Arc::new(Flat)).sample(&img, &grid);
+        assert_eq!(sampled.dimensions(), (3, 2));
+        assert_eq!(sampled.to_luma8().get_pixel(2, 1)[0], 7);
+    }
+}
@@ -0,0 +1,149 @@
+//! Output size: columns, rows and how the image fits between them
+
+use anyhow::Result;
+use std::io::IsTerminal;
+
+/// How the image is fitted to the requested columns and rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fit {
+    /// `cols` wide, with rows following the image's aspect ratio (default)
+    #[default]
+    Width,
+    /// `rows` tall, with columns following the image's aspect ratio
+    Height,
+    /// The largest size within `cols`×`rows` that keeps the aspect ratio
+    Contain,
+    /// Exactly `cols`×`rows`, keeping the aspect ratio by cropping the overflow
+    Cover,
+    /// Exactly `cols`×`rows`, distorting the aspect ratio
+    Stretch,
+}
+
+/// Select how the image fits a `cols`×`rows` box based on its CLI name
+pub fn select_fit(name: &str) -> Result<Fit> {
+    Ok(match name {
+        "contain" => Fit::Contain,
+        "cover" => Fit::Cover,
+        "stretch" => Fit::Stretch,
+        other => anyhow::bail!("unknown fit: {other}. Available options: contain, cover, stretch"),
+    })
+}
+
+/// Resolve the `--cols`/`--rows`/`--fit` options. With both sizes the image is
+/// fitted to the box; with one, the other follows the aspect ratio. With
+/// neither, `fallback` (e.g. the terminal size) is fitted, or else the image
+/// is `default_cols` wide.
+pub fn resolve_size(
+    cols: Option<u32>,
+    rows: Option<u32>,
+    fit: Fit,
+    fallback: Option<(u32, u32)>,
+    default_cols: u32,
+) -> (u32, Option<u32>, Fit) {
+    match (cols, rows, fallback) {
+        (Some(cols), Some(rows), _) => (cols, Some(rows), fit),
+        (Some(cols), None, _) => (cols, None, Fit::Width),
+        (None, Some(rows), _) => (default_cols, Some(rows), Fit::Height),
+        (None, None, Some((cols, rows))) => (cols, Some(rows), fit),
+        (None, None, None) => (default_cols, None, Fit::Width),
+    }
+}
+
+/// Columns and rows available for output when stdout is a terminal, keeping
+/// the last row free for the prompt
+pub fn terminal_size() -> Option<(u32, u32)> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let (cols, rows) = crossterm::terminal::size().ok()?;
+    (cols > 0 && rows > 1).then(|| (cols as u32, rows as u32 - 1))
+}
+
+/// Where a `w`×`h` image lands on the cell grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub cols: u32,
+    pub rows: u32,
+    /// Cells per source pixel horizontally
+    pub scale: f32,
+    /// Height/width ratio of the source area under one cell, relative to `scale`
+    pub cell_aspect: f32,
+    /// Source region `(x, y, width, height)` shown when [`Fit::Cover`] crops
+    pub crop: Option<(u32, u32, u32, u32)>,
+}
+
+/// Lay out a `w`×`h` image on cells `cell_aspect` times taller than wide,
+/// within `cols` columns and (for every fit but [`Fit::Width`]) `rows` rows
+pub fn layout((w, h): (u32, u32), cols: u32, rows: Option<u32>, fit: Fit, cell_aspect: f32) -> Layout {
+    let rows = rows.unwrap_or(1).max(1);
+    let by_width = |cols: u32| {
+        let scale = cols as f32 / w as f32;
+        let rows = ((h as f32 * scale) / cell_aspect).max(1.0).round() as u32;
+        Layout { cols, rows, scale, cell_aspect, crop: None }
+    };
+    let by_height = |rows: u32| {
+        let scale = rows as f32 * cell_aspect / h as f32;
+        let cols = (w as f32 * scale).max(1.0).round() as u32;
+        Layout { cols, rows, scale, cell_aspect, crop: None }
+    };
+    let width_limited = cols as f32 / w as f32 <= rows as f32 * cell_aspect / h as f32;
+
+    match fit {
+        Fit::Width => by_width(cols),
+        Fit::Height => by_height(rows),
+        Fit::Contain if width_limited => Layout { rows: by_width(cols).rows.min(rows), ..by_width(cols) },
+        Fit::Contain => Layout { cols: by_height(rows).cols.min(cols), ..by_height(rows) },
+        Fit::Stretch => {
+            let scale = cols as f32 / w as f32;
+            Layout { cols, rows, scale, cell_aspect: h as f32 * scale / rows as f32, crop: None }
+        }
+        Fit::Cover => {
+            // Scale so the image covers the box, then center the box on it
+            let scale = (cols as f32 / w as f32).max(rows as f32 * cell_aspect / h as f32);
+            let crop_w = ((cols as f32 / scale).round() as u32).clamp(1, w);
+            let crop_h = ((rows as f32 * cell_aspect / scale).round() as u32).clamp(1, h);
+            let scale = cols as f32 / crop_w as f32;
+            Layout {
+                cols,
+                rows,
+                scale,
+                cell_aspect: crop_h as f32 * scale / rows as f32,
+                crop: Some(((w - crop_w) / 2, (h - crop_h) / 2, crop_w, crop_h)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_fits() {
+        // 400×100 image on 2:1 cells: 80 columns give 10 rows
+        let width = layout((400, 100), 80, None, Fit::Width, 2.0);
+        assert_eq!((width.cols, width.rows, width.crop), (80, 10, None));
+        let height = layout((400, 100), 0, Some(10), Fit::Height, 2.0);
+        assert_eq!((height.cols, height.rows), (80, 10));
+
+        // Box 40×40: width limits contain, height limits cover
+        let contain = layout((400, 100), 40, Some(40), Fit::Contain, 2.0);
+        assert_eq!((contain.cols, contain.rows), (40, 5));
+        let contain = layout((400, 100), 400, Some(5), Fit::Contain, 2.0);
+        assert_eq!((contain.cols, contain.rows), (40, 5));
+        let cover = layout((400, 100), 40, Some(40), Fit::Cover, 2.0);
+        assert_eq!((cover.cols, cover.rows, cover.crop), (40, 40, Some((175, 0, 50, 100))));
+        let stretch = layout((400, 100), 40, Some(40), Fit::Stretch, 2.0);
+        assert_eq!((stretch.cols, stretch.rows), (40, 40));
+        assert!((stretch.cell_aspect - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_resolve_size() {
+        assert_eq!(resolve_size(None, None, Fit::Contain, None, 120), (120, None, Fit::Width));
+        assert_eq!(resolve_size(Some(80), None, Fit::Cover, Some((100, 30)), 120), (80, None, Fit::Width));
+        assert_eq!(resolve_size(None, Some(30), Fit::Contain, None, 120).2, Fit::Height);
+        assert_eq!(resolve_size(None, None, Fit::Cover, Some((100, 30)), 120), (100, Some(30), Fit::Cover));
+        assert!(select_fit("fill").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! Shape-matching glyph selection: pick the glyph whose bitmap best matches
+//! a cell's source patch, so line glyphs follow real contours
+
+use crate::font::glyph_bitmap;
+use std::sync::OnceLock;
+
+/// Side length of the square patch sampled per cell (matches the bundled font)
+pub const PATCH_SIZE: u32 = 8;
+
+/// SSIM stabilizing constants for values in [0, 1]
+const C1: f32 = 0.01 * 0.01;
+const C2: f32 = 0.03 * 0.03;
+
+/// A candidate glyph with its rasterized pixels and precomputed statistics
+struct Candidate {
+    glyph: char,
+    pixels: [f32; 64],
+    mean: f32,
+    variance: f32,
+}
+
+/// Printable ASCII, rasterized once from the bundled font
+fn candidates() -> &'static [Candidate] {
+    static CANDIDATES: OnceLock<Vec<Candidate>> = OnceLock::new();
+    CANDIDATES.get_or_init(|| {
+        (' '..='~')
+            .filter_map(|glyph| {
+                let bitmap = glyph_bitmap(glyph)?;
+                let mut pixels = [0.0; 64];
+                for (i, p) in pixels.iter_mut().enumerate() {
+                    *p = (bitmap[i / 8] >> (i % 8) & 1) as f32;
+                }
+                let pixels = blur(&pixels);
+                let (mean, variance) = stats(&pixels);
+                Some(Candidate { glyph, pixels, mean, variance })
+            })
+            .collect()
+    })
+}
+
+/// 3×3 box blur with clamped edges. Comparing blurred patches makes ink
+/// density count, so flat areas pick glyphs of matching weight instead of
+/// whichever glyph happens to correlate with faint noise.
+fn blur(pixels: &[f32; 64]) -> [f32; 64] {
+    let mut out = [0.0; 64];
+    for (i, o) in out.iter_mut().enumerate() {
+        let (x, y) = ((i % 8) as i32, (i / 8) as i32);
+        let mut sum = 0.0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (sx, sy) = ((x + dx).clamp(0, 7), (y + dy).clamp(0, 7));
+                sum += pixels[(sy * 8 + sx) as usize];
+            }
+        }
+        *o = sum / 9.0;
+    }
+    out
+}
+
+fn stats(pixels: &[f32; 64]) -> (f32, f32) {
+    let mean = pixels.iter().sum::<f32>() / 64.0;
+    let variance = pixels.iter().map(|p| (p - mean).powi(2)).sum::<f32>() / 64.0;
+    (mean, variance)
+}
+
+/// Structural similarity (single window) between a patch and a candidate glyph
+fn ssim(patch: &[f32; 64], patch_mean: f32, patch_var: f32, candidate: &Candidate) -> f32 {
+    let covariance = patch
+        .iter()
+        .zip(&candidate.pixels)
+        .map(|(p, g)| (p - patch_mean) * (g - candidate.mean))
+        .sum::<f32>()
+        / 64.0;
+    ((2.0 * patch_mean * candidate.mean + C1) * (2.0 * covariance + C2))
+        / ((patch_mean.powi(2) + candidate.mean.powi(2) + C1) * (patch_var + candidate.variance + C2))
+}
+
+/// Choose the glyph whose bitmap is structurally closest to `patch`,
+/// a row-major 8×8 patch of luminance values in [0, 1] (1 = bright ink)
+pub fn best_glyph(patch: &[f32; 64]) -> char {
+    let patch = blur(patch);
+    let (mean, variance) = stats(&patch);
+    candidates()
+        .iter()
+        .map(|c| (c.glyph, ssim(&patch, mean, variance, c)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(glyph, _)| glyph)
+        .unwrap_or(' ')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch_from(set: impl Fn(usize, usize) -> bool) -> [f32; 64] {
+        let mut patch = [0.0; 64];
+        for (i, p) in patch.iter_mut().enumerate() {
+            *p = if set(i % 8, i / 8) { 1.0 } else { 0.0 };
+        }
+        patch
+    }
+
+    #[test]
+    fn test_best_glyph_follows_lines() {
+        assert_eq!(best_glyph(&patch_from(|x, _| x == 3 || x == 4)), '|');
+        assert_eq!(best_glyph(&patch_from(|_, y| y == 7)), '_');
+        assert_eq!(best_glyph(&patch_from(|x, y| y < 7 && (x + y == 5 || x + y == 6))), '/');
+        assert_eq!(best_glyph(&patch_from(|_, _| false)), ' ');
+    }
+}
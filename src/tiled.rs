@@ -0,0 +1,464 @@
+//! Strip-by-strip decoding of images too large to hold in memory: rows are
+//! area-averaged down to a working resolution as they are decoded
+
+use anyhow::{bail, Context, Result};
+use crate::area::{spans, Span};
+use crate::image_processing::{sample_grid_size, AsciiConfig};
+use crate::preprocess::{exif_orientation, swaps_dimensions, Preprocess};
+use crate::sampler::Resizer;
+use image::metadata::Orientation;
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageReader, RgbImage, RgbaImage};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use tiff::decoder::{ChunkType, Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::Tag;
+
+/// Working image resolution relative to the sample grid, so the configured
+/// resizer still has detail to filter
+const OVERSAMPLE: f64 = 4.0;
+
+/// Bytes per pixel the conversion pipeline allocates on top of the decoded
+/// image (alpha flattening, linear-light and grayscale copies)
+const PIPELINE_BYTES_PER_PIXEL: u64 = 16;
+
+/// Rows of an image, decoded a strip at a time
+trait StripSource {
+    fn dimensions(&self) -> (u32, u32);
+    /// Interleaved channels per pixel: gray, gray+alpha, RGB or RGBA
+    fn channels(&self) -> usize;
+    /// Bytes held while decoding one strip
+    fn strip_bytes(&self) -> u64;
+    /// Decode the next strip, passing each of its rows (samples scaled to
+    /// 0–255) to `row`. Returns `false` once the image is exhausted.
+    fn next_strip(&mut self, row: &mut dyn FnMut(&[f32])) -> Result<bool>;
+}
+
+/// Decode an image for conversion with `config`, holding at most about
+/// `max_memory` bytes. Images that fit are decoded whole as usual; larger
+/// PNG, TIFF and binary PGM/PPM files are decoded in strips and area-averaged
+/// to a few times the sample grid's resolution, or less if the budget needs it.
+/// `preprocess` is applied to the result, with pixel crops scaled to match.
+pub fn decode_within(path: &Path, config: &AsciiConfig, preprocess: &Preprocess, max_memory: u64) -> Result<DynamicImage> {
+    let display = path.display();
+    let mut source = match open_strips(path).with_context(|| format!("failed to open {display}"))? {
+        Some(source) => source,
+        None => return decode_whole(path, preprocess, max_memory, 4, "strip decoding supports PNG, TIFF and binary PGM/PPM"),
+    };
+    let (w, h) = source.dimensions();
+    let channels = source.channels() as u64;
+    if w as u64 * h as u64 * (channels + PIPELINE_BYTES_PER_PIXEL) <= max_memory {
+        return decode_whole(path, preprocess, max_memory, channels, "");
+    }
+    if matches!(config.resizer, Resizer::OneToOne) {
+        bail!("{display} ({w}×{h}) does not fit in --max-memory with the 1to1 resizer, which keeps every pixel");
+    }
+
+    // The grid is sized for the preprocessed image, which keeps the source's
+    // pixel scale
+    let orientation = match preprocess.auto_orient {
+        true => exif_orientation(path).with_context(|| format!("failed to read {display}"))?,
+        false => Orientation::NoTransforms,
+    };
+    let oriented = if swaps_dimensions(orientation) { (h, w) } else { (w, h) };
+    let (pre_w, pre_h) = preprocess.output_dimensions(oriented)?;
+
+    // Shrink to a few samples per grid sample, then further until the working
+    // image fits in what the strip buffers leave of the budget
+    let (grid_w, grid_h) = sample_grid_size(config, (pre_w, pre_h));
+    let working = source.strip_bytes() + w as u64 * channels * 4 + (grid_w as f64 * OVERSAMPLE) as u64 * channels * 12;
+    let Some(available) = max_memory.checked_sub(working).filter(|&a| a > 0) else {
+        bail!("--max-memory is too small: decoding {display} in strips needs about {} KiB", working.div_ceil(1024));
+    };
+    let per_pixel = (channels + PIPELINE_BYTES_PER_PIXEL) as f64;
+    let factor = (pre_w as f64 / (grid_w as f64 * OVERSAMPLE))
+        .max((w as f64 * h as f64 * per_pixel / available as f64).sqrt())
+        .max(1.0);
+    let (out_w, out_h) = (((w as f64 / factor).round() as u32).max(1), ((h as f64 / factor).round() as u32).max(1));
+    if ((pre_w as f64 / factor).round() as u32) < grid_w || ((pre_h as f64 / factor).round() as u32) < grid_h {
+        bail!("--max-memory is too small for a {grid_w}×{grid_h} sample grid; reduce --cols or raise the budget");
+    }
+
+    let mut reducer = Reducer::new((w, h), (out_w, out_h), source.channels());
+    while source.next_strip(&mut |row| reducer.push_row(row))? {}
+    let mut img = reducer.finish().with_context(|| format!("{display} ended before its last row"))?;
+    img.apply_orientation(orientation);
+    preprocess.scaled(out_w as f64 / w as f64).apply(img)
+}
+
+/// Decode and preprocess the whole image, if its pixels fit in the budget
+/// with `channels` bytes each for the decoded image
+fn decode_whole(path: &Path, preprocess: &Preprocess, max_memory: u64, channels: u64, hint: &str) -> Result<DynamicImage> {
+    let display = path.display();
+    let reader = ImageReader::open(path).with_context(|| format!("failed to open {display}"))?;
+    let (w, h) = reader.with_guessed_format()?.into_dimensions().with_context(|| format!("failed to decode {display}"))?;
+    if w as u64 * h as u64 * (channels + PIPELINE_BYTES_PER_PIXEL) > max_memory {
+        bail!("{display} ({w}×{h}) does not fit in --max-memory; {hint}");
+    }
+    preprocess.open(path)
+}
+
+/// Open a strip decoder for the file's format, or `None` if the format (or
+/// this particular file's layout) cannot be decoded in strips
+fn open_strips(path: &Path) -> Result<Option<Box<dyn StripSource>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let magic: Vec<u8> = reader.fill_buf()?.iter().take(4).copied().collect();
+    Ok(match magic.as_slice() {
+        [0x89, b'P', b'N', b'G', ..] => PngStrips::open(reader)?.map(|s| Box::new(s) as Box<dyn StripSource>),
+        [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] | [b'I', b'I', 43, 0, ..] | [b'M', b'M', 0, 43, ..] => {
+            TiffStrips::open(reader)?.map(|s| Box::new(s) as Box<dyn StripSource>)
+        }
+        [b'P', b'5' | b'6', ..] => Some(Box::new(PnmStrips::open(reader)?)),
+        _ => None,
+    })
+}
+
+/// PNG, one row per strip; interlaced files need the whole image
+struct PngStrips {
+    reader: png::Reader<BufReader<File>>,
+    channels: usize,
+    row: Vec<f32>,
+}
+
+impl PngStrips {
+    fn open(reader: BufReader<File>) -> Result<Option<Self>> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let reader = decoder.read_info()?;
+        if reader.info().interlaced {
+            return Ok(None);
+        }
+        let channels = reader.output_color_type().0.samples();
+        Ok(Some(Self { reader, channels, row: Vec::new() }))
+    }
+}
+
+impl StripSource for PngStrips {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.reader.info().width, self.reader.info().height)
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn strip_bytes(&self) -> u64 {
+        // The decoded row, its predecessor for unfiltering, and the inflate window
+        self.reader.info().width as u64 * self.channels as u64 * 2 + (64 << 10)
+    }
+
+    fn next_strip(&mut self, row: &mut dyn FnMut(&[f32])) -> Result<bool> {
+        let Some(data) = self.reader.next_row()? else { return Ok(false) };
+        self.row.clear();
+        self.row.extend(data.data().iter().map(|&v| v as f32));
+        row(&self.row);
+        Ok(true)
+    }
+}
+
+/// TIFF, one strip or one row of tiles at a time
+struct TiffStrips {
+    decoder: TiffDecoder<BufReader<File>>,
+    dims: (u32, u32),
+    channels: usize,
+    /// Bytes per decoded sample
+    depth: u64,
+    tiled: bool,
+    chunk: (u32, u32),
+    /// First image row of the next strip
+    y: u32,
+    band: Vec<f32>,
+}
+
+impl TiffStrips {
+    fn open(reader: BufReader<File>) -> Result<Option<Self>> {
+        let mut decoder = TiffDecoder::new(reader)?;
+        let dims = decoder.dimensions()?;
+        let (channels, bits) = match decoder.colortype()? {
+            tiff::ColorType::Gray(bits @ (8 | 16)) => (1, bits),
+            tiff::ColorType::GrayA(bits @ (8 | 16)) => (2, bits),
+            tiff::ColorType::RGB(bits @ (8 | 16)) => (3, bits),
+            tiff::ColorType::RGBA(bits @ (8 | 16)) => (4, bits),
+            _ => return Ok(None),
+        };
+        // Separate planes per channel would need every plane of a strip at once
+        if decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration)? == Some(2) {
+            return Ok(None);
+        }
+        let tiled = decoder.get_chunk_type() == ChunkType::Tile;
+        // Strips may be declared taller than the image
+        let (chunk_w, chunk_h) = decoder.chunk_dimensions();
+        let chunk = (chunk_w, if tiled { chunk_h } else { chunk_h.min(dims.1) });
+        let depth = bits as u64 / 8;
+        Ok(Some(Self { decoder, dims, channels, depth, tiled, chunk, y: 0, band: Vec::new() }))
+    }
+}
+
+impl StripSource for TiffStrips {
+    fn dimensions(&self) -> (u32, u32) {
+        self.dims
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn strip_bytes(&self) -> u64 {
+        // One decoded chunk, plus the band of float rows it is copied into
+        let samples = self.chunk.1 as u64 * self.channels as u64;
+        samples * self.chunk.0 as u64 * self.depth + samples * self.dims.0 as u64 * 4
+    }
+
+    fn next_strip(&mut self, row: &mut dyn FnMut(&[f32])) -> Result<bool> {
+        let (w, h) = self.dims;
+        if self.y >= h {
+            return Ok(false);
+        }
+        let (chunk_w, chunk_h) = self.chunk;
+        let rows = chunk_h.min(h - self.y) as usize;
+        let stride = w as usize * self.channels;
+        self.band.clear();
+        self.band.resize(rows * stride, 0.0);
+
+        let chunk_row = self.y / chunk_h;
+        let chunks_across = if self.tiled { w.div_ceil(chunk_w) } else { 1 };
+        for tx in 0..chunks_across {
+            let index = chunk_row * chunks_across + tx;
+            let (data_w, data_h) = self.decoder.chunk_data_dimensions(index);
+            let data = self.decoder.read_chunk(index)?;
+            let x0 = (tx * chunk_w) as usize * self.channels;
+            let row_len = data_w as usize * self.channels;
+            for r in 0..(data_h as usize).min(rows) {
+                let dst = &mut self.band[r * stride + x0..][..row_len];
+                match &data {
+                    DecodingResult::U8(v) => dst.iter_mut().zip(&v[r * row_len..]).for_each(|(d, &s)| *d = s as f32),
+                    DecodingResult::U16(v) => dst.iter_mut().zip(&v[r * row_len..]).for_each(|(d, &s)| *d = s as f32 / 257.0),
+                    _ => bail!("unsupported TIFF sample format"),
+                }
+            }
+        }
+        for r in 0..rows {
+            row(&self.band[r * stride..(r + 1) * stride]);
+        }
+        self.y += rows as u32;
+        Ok(true)
+    }
+}
+
+/// Binary PGM (`P5`) or PPM (`P6`), one row per strip
+struct PnmStrips {
+    reader: BufReader<File>,
+    dims: (u32, u32),
+    channels: usize,
+    maxval: u32,
+    y: u32,
+    raw: Vec<u8>,
+    row: Vec<f32>,
+}
+
+impl PnmStrips {
+    fn open(mut reader: BufReader<File>) -> Result<Self> {
+        let mut magic = [0u8; 2];
+        reader.read_exact(&mut magic)?;
+        let channels = if magic[1] == b'5' { 1 } else { 3 };
+        let width = pnm_number(&mut reader)?;
+        let height = pnm_number(&mut reader)?;
+        let maxval = pnm_number(&mut reader)?;
+        if width == 0 || height == 0 || !(1..=65535).contains(&maxval) {
+            bail!("invalid PNM header");
+        }
+        // Exactly one whitespace byte separates the header from the pixels
+        reader.read_exact(&mut [0u8])?;
+        Ok(Self { reader, dims: (width, height), channels, maxval, y: 0, raw: Vec::new(), row: Vec::new() })
+    }
+}
+
+/// Read one decimal header field, skipping whitespace and `#` comments
+fn pnm_number(reader: &mut impl BufRead) -> Result<u32> {
+    let mut byte = [0u8];
+    loop {
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'#' => {
+                reader.read_until(b'\n', &mut Vec::new())?;
+            }
+            b if b.is_ascii_whitespace() => {}
+            _ => break,
+        }
+    }
+    let mut value = 0u32;
+    while byte[0].is_ascii_digit() {
+        value = value.checked_mul(10).and_then(|v| v.checked_add((byte[0] - b'0') as u32)).context("PNM header value too large")?;
+        if reader.fill_buf()?.first().is_none_or(|b| !b.is_ascii_digit()) {
+            return Ok(value);
+        }
+        reader.read_exact(&mut byte)?;
+    }
+    bail!("invalid PNM header")
+}
+
+impl StripSource for PnmStrips {
+    fn dimensions(&self) -> (u32, u32) {
+        self.dims
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn strip_bytes(&self) -> u64 {
+        self.dims.0 as u64 * self.channels as u64 * 2
+    }
+
+    fn next_strip(&mut self, row: &mut dyn FnMut(&[f32])) -> Result<bool> {
+        if self.y >= self.dims.1 {
+            return Ok(false);
+        }
+        let wide = self.maxval > 255;
+        let samples = self.dims.0 as usize * self.channels;
+        self.raw.resize(samples * if wide { 2 } else { 1 }, 0);
+        self.reader.read_exact(&mut self.raw)?;
+        let scale = 255.0 / self.maxval as f32;
+        self.row.clear();
+        match wide {
+            true => self.row.extend(self.raw.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 * scale)),
+            false => self.row.extend(self.raw.iter().map(|&b| b as f32 * scale)),
+        }
+        row(&self.row);
+        self.y += 1;
+        Ok(true)
+    }
+}
+
+/// Area-averages source rows, as they arrive, into a smaller image
+struct Reducer {
+    out: (u32, u32),
+    channels: usize,
+    xs: Vec<Span>,
+    ys: Vec<Span>,
+    /// Next source row
+    y: usize,
+    /// Output row being accumulated; the row after it may already have begun
+    next: usize,
+    acc: [Vec<f32>; 2],
+    reduced: Vec<f32>,
+    pixels: Vec<u8>,
+}
+
+impl Reducer {
+    fn new((w, h): (u32, u32), (out_w, out_h): (u32, u32), channels: usize) -> Self {
+        let (kx, ky) = (w as f32 / out_w as f32, h as f32 / out_h as f32);
+        let len = out_w as usize * channels;
+        Self {
+            out: (out_w, out_h),
+            channels,
+            xs: spans(out_w, w, |x| (x as f32 * kx, (x + 1) as f32 * kx)),
+            ys: spans(out_h, h, |y| (y as f32 * ky, (y + 1) as f32 * ky)),
+            y: 0,
+            next: 0,
+            acc: [vec![0.0; len], vec![0.0; len]],
+            reduced: vec![0.0; len],
+            pixels: Vec::with_capacity(len * out_h as usize),
+        }
+    }
+
+    fn push_row(&mut self, row: &[f32]) {
+        let channels = self.channels;
+        for (o, span) in self.xs.iter().enumerate() {
+            let total: f32 = span.weights.iter().sum();
+            for c in 0..channels {
+                let sum: f32 = span.weights.iter().enumerate().map(|(i, w)| w * row[(span.first + i) * channels + c]).sum();
+                self.reduced[o * channels + c] = sum / total;
+            }
+        }
+
+        // Each output row covers at least one source row, so a source row
+        // touches at most the current output row and the one after
+        for (k, acc) in self.acc.iter_mut().enumerate() {
+            let Some(span) = self.ys.get(self.next + k) else { break };
+            if let Some(weight) = self.y.checked_sub(span.first).and_then(|i| span.weights.get(i)) {
+                acc.iter_mut().zip(&self.reduced).for_each(|(a, r)| *a += weight * r);
+            }
+        }
+
+        let finished = self.ys.get(self.next).is_some_and(|span| self.y + 1 == span.first + span.weights.len());
+        if finished {
+            let total: f32 = self.ys[self.next].weights.iter().sum();
+            self.pixels.extend(self.acc[0].iter().map(|a| (a / total).round().clamp(0.0, 255.0) as u8));
+            self.acc.swap(0, 1);
+            self.acc[1].fill(0.0);
+            self.next += 1;
+        }
+        self.y += 1;
+    }
+
+    fn finish(self) -> Result<DynamicImage> {
+        let (w, h) = self.out;
+        if self.next < h as usize {
+            bail!("image data is truncated");
+        }
+        Ok(match self.channels {
+            1 => DynamicImage::ImageLuma8(GrayImage::from_raw(w, h, self.pixels).context("reduced image size")?),
+            2 => DynamicImage::ImageLumaA8(GrayAlphaImage::from_raw(w, h, self.pixels).context("reduced image size")?),
+            3 => DynamicImage::ImageRgb8(RgbImage::from_raw(w, h, self.pixels).context("reduced image size")?),
+            _ => DynamicImage::ImageRgba8(RgbaImage::from_raw(w, h, self.pixels).context("reduced image size")?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    #[test]
+    fn test_reducer_averages_areas() {
+        let mut reducer = Reducer::new((4, 4), (2, 2), 1);
+        for y in 0..4 {
+            let row: Vec<f32> = (0..4).map(|x| (x * 10 + y * 50) as f32).collect();
+            reducer.push_row(&row);
+        }
+        let reduced = reducer.finish().unwrap().to_luma8();
+        assert_eq!(reduced.as_raw(), &vec![30, 50, 130, 150]);
+    }
+
+    #[test]
+    fn test_strip_decoding_matches_whole_image() {
+        let img = DynamicImage::ImageRgb8(ImageBuffer::from_fn(1200, 600, |x, y| Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) / 8) as u8])));
+        let dir = std::env::temp_dir();
+        let config = AsciiConfig { cols: 30, ..AsciiConfig::default() };
+        for ext in ["png", "tiff", "ppm"] {
+            let path = dir.join(format!("rust-ascii-tiled-{}.{ext}", std::process::id()));
+            match ext {
+                // Written by hand: `image` saves `.ppm` files as PAM
+                "ppm" => {
+                    let mut data = b"P6\n# comment\n1200 600\n255\n".to_vec();
+                    data.extend_from_slice(img.as_bytes());
+                    std::fs::write(&path, data).unwrap();
+                }
+                _ => img.save(&path).unwrap(),
+            }
+            let reduced = decode_within(&path, &config, &Preprocess::default(), 8 << 20);
+            std::fs::remove_file(&path).unwrap();
+
+            // 30 columns oversampled 4× from 1200 pixels: reduced by 10
+            let reduced = reduced.unwrap().to_rgb8();
+            assert_eq!(reduced.dimensions(), (120, 60));
+            let expected = img.resize_exact(120, 60, image::imageops::FilterType::Triangle).to_rgb8();
+            let diff = reduced.pixels().zip(expected.pixels()).map(|(a, b)| a[2].abs_diff(b[2]) as u32).max().unwrap();
+            assert!(diff <= 8, "{ext}: {diff}");
+        }
+    }
+
+    #[test]
+    fn test_gray_image_that_fits_is_decoded_whole() {
+        // 17 bytes a pixel with one channel fit the budget, 20 with four would not
+        let path = std::env::temp_dir().join(format!("rust-ascii-tiled-gray-{}.png", std::process::id()));
+        GrayImage::from_fn(100, 100, |x, y| image::Luma([(x + y) as u8])).save(&path).unwrap();
+        let config = AsciiConfig { cols: 10, ..AsciiConfig::default() };
+        let decoded = decode_within(&path, &config, &Preprocess::default(), 100 * 100 * 18);
+        std::fs::remove_file(&path).unwrap();
+        let decoded = decoded.unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (100, 100));
+    }
+}
@@ -0,0 +1,205 @@
+//! Tone mapping of sampled luminance before glyph selection
+
+use anyhow::{bail, Result};
+use image::GrayImage;
+
+/// Tone controls applied to the sampled luminance grid.
+///
+/// Histogram steps (auto-levels, then equalization) run first, followed by
+/// contrast and brightness, gamma, and finally inversion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToneConfig {
+    /// Gamma exponent; values above 1.0 brighten midtones, below 1.0 darken them
+    pub gamma: f32,
+    /// Offset added to luminance, from -1.0 (black) to 1.0 (white)
+    pub brightness: f32,
+    /// Contrast multiplier around mid-gray; 1.0 leaves contrast unchanged
+    pub contrast: f32,
+    /// Flip dark and light, for light-background terminals and pages
+    pub invert: bool,
+    /// Stretch levels so this percentage of samples clips at each end
+    pub auto_levels: Option<f32>,
+    /// Spread luminance evenly with global histogram equalization
+    pub equalize: bool,
+}
+
+impl Default for ToneConfig {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            invert: false,
+            auto_levels: None,
+            equalize: false,
+        }
+    }
+}
+
+impl ToneConfig {
+    /// Check that every control is within its usable range
+    pub fn validate(&self) -> Result<()> {
+        if !(self.gamma > 0.0 && self.gamma.is_finite()) {
+            bail!("--gamma must be positive");
+        }
+        if !(-1.0..=1.0).contains(&self.brightness) {
+            bail!("--brightness should be between -1.0 and 1.0");
+        }
+        if !(self.contrast >= 0.0 && self.contrast.is_finite()) {
+            bail!("--contrast must not be negative");
+        }
+        if let Some(percent) = self.auto_levels {
+            if !(0.0..50.0).contains(&percent) {
+                bail!("--auto-levels should be between 0 and 50 percent");
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether applying this config would leave every value unchanged
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Tone-map a luminance grid in place
+    pub fn apply(&self, gray: &mut GrayImage) {
+        if self.is_identity() {
+            return;
+        }
+        let hist = match self.needs_histogram() {
+            true => histogram(gray.pixels().map(|p| p[0])),
+            false => [0; 256],
+        };
+        let lut = self.lut(&hist);
+        for p in gray.pixels_mut() {
+            p[0] = lut[p[0] as usize];
+        }
+    }
+
+    /// Whether the mapping depends on the grid's histogram
+    pub(crate) fn needs_histogram(&self) -> bool {
+        self.auto_levels.is_some() || self.equalize
+    }
+
+    /// Build the combined lookup table for a grid with this histogram
+    pub(crate) fn lut(&self, hist: &[u32; 256]) -> [u8; 256] {
+        let mut lut: [u8; 256] = std::array::from_fn(|v| v as u8);
+
+        if let Some(percent) = self.auto_levels {
+            let total: u32 = hist.iter().sum();
+            let clip = (total as f32 * percent / 100.0) as u32;
+            let (lo, hi) = (percentile(hist, clip), 255 - percentile_rev(hist, clip));
+            if hi > lo {
+                for (v, out) in lut.iter_mut().enumerate() {
+                    let stretched = (v as f32 - lo as f32) / (hi - lo) as f32 * 255.0;
+                    *out = stretched.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+
+        if self.equalize {
+            // Histogram of the levels after stretching
+            let mut levelled = [0u32; 256];
+            for (v, &h) in hist.iter().enumerate() {
+                levelled[lut[v] as usize] += h;
+            }
+            let mut cdf = [0u32; 256];
+            let mut running = 0;
+            for (c, h) in cdf.iter_mut().zip(levelled) {
+                running += h;
+                *c = running;
+            }
+            let cdf_min = cdf.iter().copied().find(|&c| c > 0).unwrap_or(0);
+            let span = running.saturating_sub(cdf_min);
+            if span > 0 {
+                for out in lut.iter_mut() {
+                    let c = cdf[*out as usize].saturating_sub(cdf_min);
+                    *out = ((c as f32 / span as f32) * 255.0).round() as u8;
+                }
+            }
+        }
+
+        for out in lut.iter_mut() {
+            let mut x = *out as f32 / 255.0;
+            x = ((x - 0.5) * self.contrast + 0.5 + self.brightness).clamp(0.0, 1.0);
+            x = x.powf(1.0 / self.gamma);
+            if self.invert {
+                x = 1.0 - x;
+            }
+            *out = (x * 255.0).round() as u8;
+        }
+        lut
+    }
+}
+
+pub(crate) fn histogram(values: impl Iterator<Item = u8>) -> [u32; 256] {
+    let mut hist = [0u32; 256];
+    for v in values {
+        hist[v as usize] += 1;
+    }
+    hist
+}
+
+/// Lowest value with more than `clip` samples at or below it
+fn percentile(hist: &[u32; 256], clip: u32) -> u8 {
+    let mut running = 0;
+    for (v, &h) in hist.iter().enumerate() {
+        running += h;
+        if running > clip {
+            return v as u8;
+        }
+    }
+    255
+}
+
+/// Distance from 255 of the highest value with more than `clip` samples at or above it
+fn percentile_rev(hist: &[u32; 256], clip: u32) -> u8 {
+    let mut reversed = *hist;
+    reversed.reverse();
+    percentile(&reversed, clip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    fn ramp_image() -> GrayImage {
+        // Low-contrast ramp from 100 to 149
+        GrayImage::from_fn(50, 1, |x, _| Luma([100 + x as u8]))
+    }
+
+    #[test]
+    fn test_identity_and_invert() {
+        let mut gray = ramp_image();
+        ToneConfig::default().apply(&mut gray);
+        assert_eq!(gray, ramp_image());
+
+        let invert = ToneConfig { invert: true, ..Default::default() };
+        invert.apply(&mut gray);
+        assert_eq!(gray.get_pixel(0, 0)[0], 155);
+        assert_eq!(gray.get_pixel(49, 0)[0], 106);
+    }
+
+    #[test]
+    fn test_auto_levels_and_equalize_stretch() {
+        let mut gray = ramp_image();
+        ToneConfig { auto_levels: Some(0.0), ..Default::default() }.apply(&mut gray);
+        assert_eq!(gray.get_pixel(0, 0)[0], 0);
+        assert_eq!(gray.get_pixel(49, 0)[0], 255);
+
+        let mut gray = ramp_image();
+        ToneConfig { equalize: true, ..Default::default() }.apply(&mut gray);
+        assert_eq!(gray.get_pixel(0, 0)[0], 0);
+        assert_eq!(gray.get_pixel(49, 0)[0], 255);
+        assert!(gray.pixels().zip(gray.pixels().skip(1)).all(|(a, b)| a[0] < b[0]));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ToneConfig::default().validate().is_ok());
+        assert!(ToneConfig { gamma: 0.0, ..Default::default() }.validate().is_err());
+        assert!(ToneConfig { brightness: 2.0, ..Default::default() }.validate().is_err());
+        assert!(ToneConfig { auto_levels: Some(60.0), ..Default::default() }.validate().is_err());
+    }
+}
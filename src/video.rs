@@ -0,0 +1,274 @@
+//! Uncompressed video streams: YUV4MPEG2 (`ffmpeg -f yuv4mpegpipe`) and raw
+//! rgb24 or gray frames
+
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, GrayImage, RgbImage};
+use std::io::{BufRead, ErrorKind, Read};
+
+/// Longest Y4M stream or frame header accepted
+const MAX_HEADER: usize = 4096;
+
+/// Largest frame accepted, in bytes of pixel data; the same as the `image`
+/// crate's default allocation limit for decoders
+const MAX_FRAME_BYTES: usize = 512 * 1024 * 1024;
+
+/// Pixel layout of the frames in a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Planar Y'CbCr with chroma subsampled by `(x, y)`: (2, 2) for 4:2:0,
+    /// (2, 1) for 4:2:2 and (1, 1) for 4:4:4
+    Yuv { subsampling: (u32, u32), full_range: bool },
+    /// Luma only, as Y4M `Cmono`
+    Mono { full_range: bool },
+    /// Interleaved 8-bit RGB
+    Rgb24,
+    /// 8-bit grayscale
+    Gray,
+}
+
+/// Select a raw frame pixel format based on its CLI name (ffmpeg's `-pix_fmt`)
+pub fn select_pixel_format(name: &str) -> Result<PixelFormat> {
+    Ok(match name {
+        "rgb24" => PixelFormat::Rgb24,
+        "gray" => PixelFormat::Gray,
+        other => bail!("unknown raw pixel format: {other}. Available options: rgb24, gray"),
+    })
+}
+
+/// Parse a frame size such as `640x360`
+pub fn parse_frame_size(size: &str) -> Result<(u32, u32)> {
+    let parsed = size.split_once(['x', 'X']).and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)));
+    match parsed {
+        Some((w, h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => bail!("Invalid frame size {size:?} (use WIDTHxHEIGHT, e.g. 640x360)"),
+    }
+}
+
+/// Frames read one at a time from a YUV4MPEG2 or raw video stream
+pub struct VideoReader<R> {
+    reader: R,
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    fps: Option<(u32, u32)>,
+    y4m: bool,
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> VideoReader<R> {
+    /// Read a YUV4MPEG2 stream header. 8-bit 4:2:0, 4:2:2, 4:4:4 and mono
+    /// streams are supported; Y'CbCr is BT.601, limited range unless the
+    /// stream says `XCOLORRANGE=FULL`.
+    pub fn y4m(mut reader: R) -> Result<Self> {
+        let header = read_line(&mut reader)?.context("empty Y4M stream")?;
+        let mut tokens = header.split(' ');
+        if tokens.next() != Some("YUV4MPEG2") {
+            bail!("not a YUV4MPEG2 stream");
+        }
+        let (mut width, mut height, mut fps) = (0, 0, None);
+        let (mut colorspace, mut full_range) = ("420jpeg", None);
+        for token in tokens {
+            let Some(tag) = token.chars().next() else { continue };
+            let value = &token[tag.len_utf8()..];
+            match tag {
+                'W' => width = value.parse().context("invalid Y4M width")?,
+                'H' => height = value.parse().context("invalid Y4M height")?,
+                'F' => {
+                    // A zero rate, like F0:0 for "unknown", counts as none
+                    fps = value
+                        .split_once(':')
+                        .and_then(|(n, d)| Some((n.parse().ok()?, d.parse().ok()?)))
+                        .filter(|&(n, d)| n > 0 && d > 0);
+                }
+                'C' => colorspace = value,
+                'X' => match value {
+                    "COLORRANGE=FULL" => full_range = Some(true),
+                    "COLORRANGE=LIMITED" => full_range = Some(false),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        if width == 0 || height == 0 {
+            bail!("Y4M header has no frame size");
+        }
+        let format = match colorspace {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => PixelFormat::Yuv { subsampling: (2, 2), full_range: full_range.unwrap_or(false) },
+            "422" => PixelFormat::Yuv { subsampling: (2, 1), full_range: full_range.unwrap_or(false) },
+            "444" => PixelFormat::Yuv { subsampling: (1, 1), full_range: full_range.unwrap_or(false) },
+            "mono" => PixelFormat::Mono { full_range: full_range.unwrap_or(true) },
+            other => bail!("unsupported Y4M colorspace C{other}; convert with -pix_fmt yuv420p"),
+        };
+        Self { reader, format, width, height, fps, y4m: true, buffer: Vec::new() }.checked()
+    }
+
+    /// Headerless frames of `format` and the given size, back to back
+    pub fn raw(reader: R, format: PixelFormat, (width, height): (u32, u32)) -> Result<Self> {
+        Self { reader, format, width, height, fps: None, y4m: false, buffer: Vec::new() }.checked()
+    }
+
+    /// Reject frame sizes whose pixel data would not fit in [`MAX_FRAME_BYTES`]
+    fn checked(self) -> Result<Self> {
+        match self.frame_bytes() {
+            Some(bytes) if bytes <= MAX_FRAME_BYTES => Ok(self),
+            _ => bail!("{}x{} video frames are too large", self.width, self.height),
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frame rate as a fraction, when the stream declares one
+    pub fn fps(&self) -> Option<(u32, u32)> {
+        self.fps
+    }
+
+    /// Bytes of pixel data per frame, or `None` if that overflows
+    fn frame_bytes(&self) -> Option<usize> {
+        let luma = (self.width as usize).checked_mul(self.height as usize)?;
+        match self.format {
+            PixelFormat::Yuv { subsampling: (sx, sy), .. } => {
+                let chroma = (self.width.div_ceil(sx) as usize).checked_mul(self.height.div_ceil(sy) as usize)?;
+                luma.checked_add(chroma.checked_mul(2)?)
+            }
+            PixelFormat::Mono { .. } | PixelFormat::Gray => Some(luma),
+            PixelFormat::Rgb24 => luma.checked_mul(3),
+        }
+    }
+
+    fn read_frame(&mut self) -> Result<Option<DynamicImage>> {
+        if self.y4m {
+            match read_line(&mut self.reader)? {
+                None => return Ok(None),
+                Some(line) if line.starts_with("FRAME") => {}
+                Some(_) => bail!("Y4M frame header missing"),
+            }
+        }
+        self.buffer.resize(self.frame_bytes().expect("checked when the reader was made"), 0);
+        let filled = fill(&mut self.reader, &mut self.buffer)?;
+        if filled == 0 && !self.y4m {
+            return Ok(None);
+        }
+        if filled < self.buffer.len() {
+            bail!("video stream ended in the middle of a frame");
+        }
+
+        let (w, h, data) = (self.width, self.height, &self.buffer);
+        let index = |x: u32, y: u32, width: u32| y as usize * width as usize + x as usize;
+        Ok(Some(match self.format {
+            PixelFormat::Rgb24 => DynamicImage::ImageRgb8(RgbImage::from_raw(w, h, data.clone()).expect("frame size")),
+            PixelFormat::Gray => DynamicImage::ImageLuma8(GrayImage::from_raw(w, h, data.clone()).expect("frame size")),
+            PixelFormat::Mono { full_range } => DynamicImage::ImageLuma8(GrayImage::from_fn(w, h, |x, y| {
+                let luma = data[index(x, y, w)] as f32;
+                image::Luma([if full_range { luma as u8 } else { expand_luma(luma).round().clamp(0.0, 255.0) as u8 }])
+            })),
+            PixelFormat::Yuv { subsampling: (sx, sy), full_range } => {
+                let (luma, chroma) = data.split_at(w as usize * h as usize);
+                let (cb, cr) = chroma.split_at(chroma.len() / 2);
+                let chroma_w = w.div_ceil(sx);
+                DynamicImage::ImageRgb8(RgbImage::from_fn(w, h, |x, y| {
+                    let c = index(x / sx, y / sy, chroma_w);
+                    image::Rgb(yuv_to_rgb(luma[index(x, y, w)], cb[c], cr[c], full_range))
+                }))
+            }
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for VideoReader<R> {
+    type Item = Result<DynamicImage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Studio-swing luma (16–235) to full range
+fn expand_luma(y: f32) -> f32 {
+    (y - 16.0) * 255.0 / 219.0
+}
+
+/// BT.601 Y'CbCr to RGB
+fn yuv_to_rgb(y: u8, cb: u8, cr: u8, full_range: bool) -> [u8; 3] {
+    let (y, cb, cr) = match full_range {
+        true => (y as f32, cb as f32 - 128.0, cr as f32 - 128.0),
+        false => (expand_luma(y as f32), (cb as f32 - 128.0) * 255.0 / 224.0, (cr as f32 - 128.0) * 255.0 / 224.0),
+    };
+    let channel = |v: f32| v.round().clamp(0.0, 255.0) as u8;
+    [channel(y + 1.402 * cr), channel(y - 0.344_136 * cb - 0.714_136 * cr), channel(y + 1.772 * cb)]
+}
+
+/// Read a `\n`-terminated header line, or `None` at the end of the stream
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader.take(MAX_HEADER as u64).read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        bail!("Y4M header is unterminated or longer than {MAX_HEADER} bytes");
+    }
+    Ok(Some(String::from_utf8(line).context("Y4M header is not text")?))
+}
+
+/// Read until `buf` is full or the stream ends, returning the bytes read
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_y4m_frames() {
+        // Two 4×2 4:2:0 frames: limited-range black, then full red
+        let mut stream = b"YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C420jpeg XYSCSS=420JPEG\n".to_vec();
+        stream.extend_from_slice(b"FRAME\n");
+        stream.extend([16; 8].iter().chain(&[128; 2]).chain(&[128; 2]));
+        stream.extend_from_slice(b"FRAME Ixyz\n");
+        stream.extend([81; 8].iter().chain(&[90; 2]).chain(&[240; 2]));
+
+        let reader = VideoReader::y4m(&stream[..]).unwrap();
+        assert_eq!((reader.dimensions(), reader.fps()), ((4, 2), Some((30000, 1001))));
+        let frames: Vec<_> = reader.map(|f| f.unwrap().to_rgb8()).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].get_pixel(3, 1).0, [0, 0, 0]);
+        let red = frames[1].get_pixel(0, 0).0;
+        assert!(red[0] >= 250 && red[1] <= 2 && red[2] <= 2, "{red:?}");
+
+        // A truncated frame is an error, not a silent end
+        let truncated = VideoReader::y4m(&stream[..stream.len() - 1]).unwrap();
+        assert!(truncated.last().unwrap().is_err());
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 W4 H2 C420p10\n"[..]).is_err());
+        // Unknown tags are skipped, even ones that start with a multi-byte character
+        assert!(VideoReader::y4m("YUV4MPEG2 W4 H2 éx  F25:1\n".as_bytes()).is_ok());
+        // A zero frame rate is no frame rate, and huge frames are refused up front
+        for rate in ["F0:0", "F0:1", "F25:0"] {
+            let header = format!("YUV4MPEG2 W4 H2 {rate}\n");
+            assert_eq!(VideoReader::y4m(header.as_bytes()).unwrap().fps(), None);
+        }
+        assert!(VideoReader::y4m(&b"YUV4MPEG2 W4294967295 H4294967295\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_raw_frames() {
+        let data: Vec<u8> = (0..24).collect();
+        let frames: Vec<_> = VideoReader::raw(&data[..], PixelFormat::Rgb24, (2, 2)).unwrap().map(|f| f.unwrap()).collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].to_rgb8().get_pixel(1, 1).0, [21, 22, 23]);
+        assert_eq!(parse_frame_size("640x360").unwrap(), (640, 360));
+        assert!(parse_frame_size("640").is_err());
+        assert!(select_pixel_format("yuv420p").is_err());
+    }
+}